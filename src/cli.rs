@@ -0,0 +1,285 @@
+use std::fmt;
+
+pub const USAGE: &str = "\
+usage: apprentice <command> [options] <file>
+
+commands:
+    run      scan, parse and execute a program
+    check    scan and parse a program without executing it
+    fmt      rewrite a program in the canonical style
+    repl     start an interactive session (takes no file)
+    lsp      start a language server on stdin and stdout (takes no file)
+
+options:
+    --dump-tokens    print the scanned tokens
+    --dump-ast       print the parsed syntax tree as s-expressions
+    --dump-ast=tree  print it as an indented tree with positions
+    --dump-ast=cst   print the concrete syntax tree, with every token and comment
+    --format=NAME    print tokens and diagnostics as 'text' (the default) or 'json'
+    --backend=NAME   run with 'tree' (the default) or 'vm'
+    --check          with fmt, only report whether the file is formatted
+    -h, --help       print this message
+
+use '-' as the file to read the program from stdin";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Run,
+    Check,
+    Fmt,
+    Repl,
+    Lsp,
+    Help,
+}
+
+//how --dump-ast prints the syntax tree
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AstFormat {
+    Sexpr,
+    Tree,
+    Cst,
+}
+
+//how tokens and diagnostics are printed, json is one object per line for tools
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+//what runs the program, walking the syntax tree or compiling it to bytecode for the vm
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    #[default]
+    Tree,
+    Vm,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub command: Command,
+    pub path: String,
+    pub dump_tokens: bool,
+    pub dump_ast: Option<AstFormat>,
+    pub backend: Backend,
+    pub format: OutputFormat,
+    //fmt leaves the file alone and fails if it isn't formatted
+    pub check: bool,
+}
+
+#[derive(Debug)]
+pub enum UsageError {
+    MissingCommand,
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingFile,
+    UnexpectedArgument(String),
+    UnknownBackend(String),
+    UnknownFormat(String),
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UsageError::MissingCommand => write!(f, "no command given"),
+            UsageError::UnknownCommand(c) => write!(f, "unknown command '{c}'"),
+            UsageError::UnknownOption(o) => write!(f, "unknown option '{o}'"),
+            UsageError::MissingFile => write!(f, "no input file given"),
+            UsageError::UnexpectedArgument(a) => write!(f, "unexpected argument '{a}'"),
+            UsageError::UnknownBackend(b) => write!(f, "unknown backend '{b}'"),
+            UsageError::UnknownFormat(o) => write!(f, "unknown output format '{o}'"),
+        }
+    }
+}
+
+//parses the command line arguments (without the program name)
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, UsageError> {
+    let mut command = None;
+    let mut path = None;
+    let mut dump_tokens = false;
+    let mut dump_ast = None;
+    let mut backend = Backend::default();
+    let mut format = OutputFormat::default();
+    let mut check = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "-h" | "--help" => command = Some(Command::Help),
+            "--dump-tokens" => dump_tokens = true,
+            "--dump-ast" | "--dump-ast=sexpr" => dump_ast = Some(AstFormat::Sexpr),
+            "--dump-ast=tree" => dump_ast = Some(AstFormat::Tree),
+            "--dump-ast=cst" => dump_ast = Some(AstFormat::Cst),
+            "--check" => check = true,
+            "--format=text" => format = OutputFormat::Text,
+            "--format=json" => format = OutputFormat::Json,
+            _ if arg.starts_with("--format=") => {
+                return Err(UsageError::UnknownFormat(
+                    arg["--format=".len()..].to_string(),
+                ))
+            }
+            "--backend=tree" => backend = Backend::Tree,
+            "--backend=vm" => backend = Backend::Vm,
+            _ if arg.starts_with("--backend=") => {
+                return Err(UsageError::UnknownBackend(
+                    arg["--backend=".len()..].to_string(),
+                ))
+            }
+            //a lone dash means stdin, so it counts as a file
+            "-" => set_positional(&mut command, &mut path, arg)?,
+            _ if arg.starts_with('-') => return Err(UsageError::UnknownOption(arg)),
+            _ => set_positional(&mut command, &mut path, arg)?,
+        }
+    }
+
+    let command = command.ok_or(UsageError::MissingCommand)?;
+    if matches!(command, Command::Help | Command::Repl | Command::Lsp) {
+        return Ok(Options {
+            command,
+            path: String::new(),
+            dump_tokens,
+            dump_ast,
+            backend,
+            format,
+            check,
+        });
+    }
+
+    Ok(Options {
+        command,
+        path: path.ok_or(UsageError::MissingFile)?,
+        dump_tokens,
+        dump_ast,
+        backend,
+        format,
+        check,
+    })
+}
+
+//the first positional argument is the command, the second the file
+fn set_positional(
+    command: &mut Option<Command>,
+    path: &mut Option<String>,
+    arg: String,
+) -> Result<(), UsageError> {
+    if command.is_none() {
+        *command = Some(match arg.as_str() {
+            "run" => Command::Run,
+            "check" => Command::Check,
+            "fmt" => Command::Fmt,
+            "repl" => Command::Repl,
+            "lsp" => Command::Lsp,
+            "help" => Command::Help,
+            _ => return Err(UsageError::UnknownCommand(arg)),
+        });
+    } else if path.is_none() {
+        *path = Some(arg);
+    } else {
+        return Err(UsageError::UnexpectedArgument(arg));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string())).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn commands() {
+        for (name, command) in [
+            ("run", Command::Run),
+            ("check", Command::Check),
+            ("fmt", Command::Fmt),
+        ] {
+            let options = parse(&[name, "main.aprn"]).unwrap();
+            assert_eq!(options.command, command);
+            assert_eq!(options.path, "main.aprn");
+        }
+        //these take no file
+        for (args, command) in [
+            (&["repl"][..], Command::Repl),
+            (&["lsp"], Command::Lsp),
+            (&["help"], Command::Help),
+            (&["--help"], Command::Help),
+            (&["run", "-h"], Command::Help),
+        ] {
+            assert_eq!(parse(args).unwrap().command, command, "{args:?}");
+        }
+        assert_eq!(parse(&[]).unwrap_err(), "no command given");
+        assert_eq!(
+            parse(&["walk", "main.aprn"]).unwrap_err(),
+            "unknown command 'walk'"
+        );
+    }
+
+    #[test]
+    fn files() {
+        assert_eq!(parse(&["run"]).unwrap_err(), "no input file given");
+        assert_eq!(
+            parse(&["check", "--dump-tokens"]).unwrap_err(),
+            "no input file given"
+        );
+        assert_eq!(
+            parse(&["run", "a.aprn", "b.aprn"]).unwrap_err(),
+            "unexpected argument 'b.aprn'"
+        );
+        //a lone dash is stdin, not an option
+        assert_eq!(parse(&["run", "-"]).unwrap().path, "-");
+        assert_eq!(parse(&["-", "run"]).unwrap_err(), "unknown command '-'");
+    }
+
+    #[test]
+    fn options() {
+        let options = parse(&["--backend=vm", "run", "--dump-tokens", "main.aprn"]).unwrap();
+        assert_eq!(options.backend, Backend::Vm);
+        assert!(options.dump_tokens);
+        assert_eq!(options.dump_ast, None);
+        assert_eq!(options.format, OutputFormat::Text);
+        assert!(!options.check);
+
+        let options = parse(&["fmt", "--check", "--format=json", "main.aprn"]).unwrap();
+        assert!(options.check);
+        assert_eq!(options.format, OutputFormat::Json);
+        assert_eq!(options.backend, Backend::Tree);
+
+        assert_eq!(
+            parse(&["run", "-x", "a"]).unwrap_err(),
+            "unknown option '-x'"
+        );
+        assert_eq!(
+            parse(&["run", "--fast", "a"]).unwrap_err(),
+            "unknown option '--fast'"
+        );
+        assert_eq!(
+            parse(&["run", "--backend=jit", "a"]).unwrap_err(),
+            "unknown backend 'jit'"
+        );
+        assert_eq!(
+            parse(&["run", "--format=xml", "a"]).unwrap_err(),
+            "unknown output format 'xml'"
+        );
+    }
+
+    #[test]
+    fn dump_ast() {
+        for (arg, format) in [
+            ("--dump-ast", AstFormat::Sexpr),
+            ("--dump-ast=sexpr", AstFormat::Sexpr),
+            ("--dump-ast=tree", AstFormat::Tree),
+            ("--dump-ast=cst", AstFormat::Cst),
+        ] {
+            assert_eq!(parse(&["check", arg, "a"]).unwrap().dump_ast, Some(format));
+        }
+        //the last one given wins
+        let options = parse(&["check", "--dump-ast=cst", "--dump-ast=tree", "a"]).unwrap();
+        assert_eq!(options.dump_ast, Some(AstFormat::Tree));
+        assert_eq!(
+            parse(&["check", "--dump-ast=json", "a"]).unwrap_err(),
+            "unknown option '--dump-ast=json'"
+        );
+    }
+}
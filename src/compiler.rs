@@ -0,0 +1,783 @@
+use std::rc::Rc;
+
+use crate::diagnostic::{Diagnostic, Span};
+use crate::parser::exprstmt::{
+    BinOpType, ClassDecl, Expr, FunctionDecl, Literal, LogicOpType, Slot, SourceLocation, Stmt,
+    Symbol, UniOpType,
+};
+use crate::vm::chunk::{Check, Chunk, Constant, OpCode, Prototype, UpvalueRef};
+
+//a program that doesn't fit in the limits of the bytecode
+#[derive(Debug)]
+pub struct Error {
+    pub message: String,
+    pub span: Span,
+}
+
+impl Error {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error("E0401", self.message.clone(), self.span)
+            .with_note("the vm backend has fixed limits, the tree backend doesn't")
+    }
+}
+
+//compiles a resolved program into the function the vm runs as the script,
+//the slots the resolver gave the variables are turned into stack slots and upvalues
+pub fn compile(stmts: &[Stmt]) -> Result<Rc<Prototype>, Error> {
+    let mut compiler = Compiler {
+        functions: vec![FunctionState::new("script", FunctionKind::Script)],
+        scopes: vec![],
+        span: Span::new(1, 1, 0),
+    };
+    for stmt in stmts {
+        compiler.statement(stmt)?;
+    }
+    compiler.emit_op(OpCode::Null);
+    compiler.emit_op(OpCode::Return);
+    let script = compiler
+        .functions
+        .pop()
+        .expect("the script is compiled last");
+    Ok(Rc::new(script.proto))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Method,
+    Initializer,
+}
+
+struct Local {
+    depth: usize,
+    //captured locals are moved into their upvalue when they go out of scope
+    captured: bool,
+    //declared without a value, so reading it checks that it has been given one
+    unset: bool,
+}
+
+//one of the resolver's scopes, its variables are numbered from base in the function's locals
+struct Scope {
+    function: usize,
+    base: usize,
+}
+
+struct Loop {
+    //the locals and try statements the loop is inside of, break and continue leave the rest
+    locals: usize,
+    handlers: usize,
+    //where continue jumps back to, None in a for loop where it jumps forward to the increment
+    continue_target: Option<usize>,
+    continues: Vec<usize>,
+    breaks: Vec<usize>,
+}
+
+//the function being compiled, one per nested function declaration
+struct FunctionState {
+    proto: Prototype,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    //0 is the global scope, which only the script has
+    scope_depth: usize,
+    loops: Vec<Loop>,
+    //how many try statements the code being compiled is inside of
+    handlers: usize,
+}
+
+impl FunctionState {
+    //slot 0 holds the function itself, or the instance in a method
+    fn new(name: &str, kind: FunctionKind) -> FunctionState {
+        FunctionState {
+            proto: Prototype {
+                name: name.to_string(),
+                is_initializer: kind == FunctionKind::Initializer,
+                ..Default::default()
+            },
+            kind,
+            locals: vec![Local {
+                depth: 0,
+                captured: false,
+                unset: false,
+            }],
+            upvalues: vec![],
+            scope_depth: if kind == FunctionKind::Script { 0 } else { 1 },
+            loops: vec![],
+            handlers: 0,
+        }
+    }
+}
+
+//where a variable lives, decided at compile time
+enum Variable {
+    Local(u8),
+    Upvalue(u8),
+    Global(u16),
+}
+
+struct Compiler {
+    functions: Vec<FunctionState>,
+    //the scopes the resolver had at the code being compiled
+    scopes: Vec<Scope>,
+    //the span given to the instructions being emitted, errors at runtime point at it
+    span: Span,
+}
+
+impl Compiler {
+    fn statement(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        match stmt {
+            Stmt::Expression(e) => {
+                self.expression(e)?;
+                self.emit_op(OpCode::Pop);
+            }
+            Stmt::Print(e) => {
+                self.expression(e)?;
+                self.emit_op(OpCode::Print);
+            }
+            //the value is left on the stack, where it becomes the local
+            //a local without an initializer holds a marker that is an error to read
+            Stmt::VarDeclaration(sym, init) => match init {
+                Some(init) => {
+                    self.expression(init)?;
+                    self.define_variable(sym)?;
+                }
+                None if self.state().scope_depth > 0 => {
+                    self.span = sym.span();
+                    self.emit_op(OpCode::Uninitialized);
+                    self.add_local(sym)?;
+                    self.state_mut()
+                        .locals
+                        .last_mut()
+                        .expect("the local was just added")
+                        .unset = true;
+                }
+                None => {
+                    self.span = sym.span();
+                    let name = self.identifier_constant(&sym.name)?;
+                    self.emit_op_u16(OpCode::DeclareGlobal, name);
+                }
+            },
+            Stmt::Block(stmts) => {
+                self.begin_scope();
+                for stmt in stmts {
+                    self.statement(stmt)?;
+                }
+                self.end_scope();
+            }
+            Stmt::If(loc, cond, then_branch, else_branch) => {
+                self.condition(cond, loc, Check::If, "if")?;
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.statement(then_branch)?;
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump)?;
+                self.emit_op(OpCode::Pop);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.patch_jump(end_jump)?;
+            }
+            Stmt::While(loc, cond, body) => {
+                let start = self.chunk().code.len();
+                self.condition(cond, loc, Check::While, "while")?;
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.begin_loop(Some(start));
+                self.statement(body)?;
+                self.emit_loop(start)?;
+                self.patch_jump(exit_jump)?;
+                self.emit_op(OpCode::Pop);
+                self.end_loop()?;
+            }
+            Stmt::For(loc, init, cond, increment, body) => {
+                //the loop variable lives in its own scope around the loop
+                self.begin_scope();
+                if let Some(init) = init {
+                    self.statement(init)?;
+                }
+                let start = self.chunk().code.len();
+                let exit_jump = match cond {
+                    Some(cond) => {
+                        self.condition(cond, loc, Check::For, "for")?;
+                        let jump = self.emit_jump(OpCode::JumpIfFalse);
+                        self.emit_op(OpCode::Pop);
+                        Some(jump)
+                    }
+                    None => None,
+                };
+                self.begin_loop(None);
+                self.statement(body)?;
+                let continues = std::mem::take(&mut self.innermost_loop().continues);
+                for jump in continues {
+                    self.patch_jump(jump)?;
+                }
+                if let Some(increment) = increment {
+                    self.expression(increment)?;
+                    self.emit_op(OpCode::Pop);
+                }
+                self.emit_loop(start)?;
+                if let Some(exit_jump) = exit_jump {
+                    self.patch_jump(exit_jump)?;
+                    self.emit_op(OpCode::Pop);
+                }
+                self.end_loop()?;
+                self.end_scope();
+            }
+            Stmt::Break => {
+                self.leave_loop();
+                let jump = self.emit_jump(OpCode::Jump);
+                self.innermost_loop().breaks.push(jump);
+            }
+            Stmt::Continue => {
+                self.leave_loop();
+                match self.innermost_loop().continue_target {
+                    Some(target) => self.emit_loop(target)?,
+                    None => {
+                        let jump = self.emit_jump(OpCode::Jump);
+                        self.innermost_loop().continues.push(jump);
+                    }
+                }
+            }
+            //the name is declared first so the function can call itself
+            Stmt::Function(decl) => {
+                if self.state().scope_depth > 0 {
+                    self.add_local(&decl.name)?;
+                }
+                self.function(decl, FunctionKind::Function)?;
+                if self.state().scope_depth == 0 {
+                    self.define_global(&decl.name)?;
+                }
+            }
+            Stmt::Class(decl) => self.class(decl)?,
+            //parsing makes sure init never returns a value
+            Stmt::Return(e) => {
+                match e {
+                    _ if self.state().kind == FunctionKind::Initializer => {
+                        self.emit_op_u8(OpCode::GetLocal, 0)
+                    }
+                    Some(e) => self.expression(e)?,
+                    None => self.emit_op(OpCode::Null),
+                }
+                self.emit_op(OpCode::Return);
+            }
+            Stmt::Throw(loc, e) => {
+                self.expression(e)?;
+                self.span = loc.span("throw".len());
+                self.emit_op(OpCode::Throw);
+            }
+            //the vm pushes the caught error where the handler's first local goes
+            Stmt::Try(body, name, handler) => {
+                let handler_jump = self.emit_jump(OpCode::PushHandler);
+                self.state_mut().handlers += 1;
+                self.begin_scope();
+                for stmt in body {
+                    self.statement(stmt)?;
+                }
+                self.end_scope();
+                self.state_mut().handlers -= 1;
+                self.emit_op(OpCode::PopHandler);
+                let end_jump = self.emit_jump(OpCode::Jump);
+
+                self.patch_jump(handler_jump)?;
+                self.begin_scope();
+                self.add_local(name)?;
+                for stmt in handler {
+                    self.statement(stmt)?;
+                }
+                self.end_scope();
+                self.patch_jump(end_jump)?;
+            }
+        }
+        Ok(())
+    }
+
+    //compiles the function into its own prototype, loaded with a Closure instruction
+    fn function(&mut self, decl: &FunctionDecl, kind: FunctionKind) -> Result<(), Error> {
+        self.functions
+            .push(FunctionState::new(&decl.name.name, kind));
+        self.state_mut().proto.arity = decl.params.len();
+        //the resolver puts 'this' in a scope of its own around the method's
+        let function = self.functions.len() - 1;
+        let scopes = self.scopes.len();
+        if matches!(kind, FunctionKind::Method | FunctionKind::Initializer) {
+            self.scopes.push(Scope { function, base: 0 });
+        }
+        self.scopes.push(Scope { function, base: 1 });
+        for param in &decl.params {
+            self.add_local(param)?;
+        }
+        for stmt in &decl.body {
+            self.statement(stmt)?;
+        }
+        self.scopes.truncate(scopes);
+        if kind == FunctionKind::Initializer {
+            self.emit_op_u8(OpCode::GetLocal, 0);
+        } else {
+            self.emit_op(OpCode::Null);
+        }
+        self.emit_op(OpCode::Return);
+
+        let mut state = self.functions.pop().expect("the function was pushed");
+        state.proto.upvalues = state.upvalues;
+        let proto = Constant::Function(Rc::new(state.proto));
+        self.span = decl.name.span();
+        let index = self.make_constant(proto)?;
+        self.emit_op_u16(OpCode::Closure, index);
+        Ok(())
+    }
+
+    //the methods are put on the stack and collected by the Class instruction
+    fn class(&mut self, decl: &ClassDecl) -> Result<(), Error> {
+        if self.state().scope_depth > 0 {
+            self.add_local(&decl.name)?;
+        }
+        if decl.methods.len() > u8::MAX as usize {
+            return Err(Error {
+                message: format!("{} has more than {} methods", decl.name.name, u8::MAX),
+                span: decl.name.span(),
+            });
+        }
+        for method in &decl.methods {
+            let kind = if method.name.name == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.function(method, kind)?;
+        }
+        self.span = decl.name.span();
+        let name = self.identifier_constant(&decl.name.name)?;
+        self.emit_op_u16(OpCode::Class, name);
+        self.emit_byte(decl.methods.len() as u8);
+        if self.state().scope_depth == 0 {
+            self.define_global(&decl.name)?;
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<(), Error> {
+        match expr {
+            Expr::Literal(lit) => match lit {
+                Literal::Number(n) => self.emit_constant(Constant::Number(*n))?,
+                Literal::String(s) => self.emit_constant(Constant::String(s.as_str().into()))?,
+                Literal::True => self.emit_op(OpCode::True),
+                Literal::False => self.emit_op(OpCode::False),
+                Literal::Null => self.emit_op(OpCode::Null),
+            },
+            Expr::Grouping(e) => self.expression(e)?,
+            Expr::Unary(op, e) => {
+                self.expression(e)?;
+                self.span = op.span();
+                self.emit_op(match op.u_type {
+                    UniOpType::Minus => OpCode::Negate,
+                    UniOpType::Bang => OpCode::Not,
+                });
+            }
+            Expr::Binary(left, op, right) => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.span = op.span();
+                self.emit_op(match op.b_type {
+                    BinOpType::EqualEqual => OpCode::Equal,
+                    BinOpType::NotEqual => OpCode::NotEqual,
+                    BinOpType::Less => OpCode::Less,
+                    BinOpType::LessEqual => OpCode::LessEqual,
+                    BinOpType::Greater => OpCode::Greater,
+                    BinOpType::GreaterEqual => OpCode::GreaterEqual,
+                    BinOpType::Add => OpCode::Add,
+                    BinOpType::Sub => OpCode::Subtract,
+                    BinOpType::Mult => OpCode::Multiply,
+                    BinOpType::Div => OpCode::Divide,
+                });
+            }
+            //short-circuits, both sides are checked to be booleans when they are evaluated
+            Expr::Logical(left, op, right) => {
+                let (check, jump) = match op.l_type {
+                    LogicOpType::And => (Check::And, OpCode::JumpIfFalse),
+                    LogicOpType::Or => (Check::Or, OpCode::JumpIfTrue),
+                };
+                self.expression(left)?;
+                self.span = op.span();
+                self.emit_op_u8(OpCode::CheckBool, check as u8);
+                let end_jump = self.emit_jump(jump);
+                self.emit_op(OpCode::Pop);
+                self.expression(right)?;
+                self.span = op.span();
+                self.emit_op_u8(OpCode::CheckBool, check as u8);
+                self.patch_jump(end_jump)?;
+            }
+            Expr::Ternary(cond, loc, then_branch, else_branch) => {
+                self.condition(cond, loc, Check::Ternary, "?")?;
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.emit_op(OpCode::Pop);
+                self.expression(then_branch)?;
+                let end_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump)?;
+                self.emit_op(OpCode::Pop);
+                self.expression(else_branch)?;
+                self.patch_jump(end_jump)?;
+            }
+            Expr::Call(callee, loc, args) => {
+                self.expression(callee)?;
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                self.span = loc.span(1);
+                if args.len() > u8::MAX as usize {
+                    return Err(self.error(format!("More than {} arguments", u8::MAX)));
+                }
+                self.emit_op_u8(OpCode::Call, args.len() as u8);
+            }
+            Expr::This(loc, slot) => {
+                self.span = loc.span("this".len());
+                self.get_variable("this", slot.get())?;
+            }
+            Expr::Variable(sym, slot) => {
+                self.span = sym.span();
+                self.get_variable(&sym.name, slot.get())?;
+            }
+            Expr::Assignment(sym, value, slot) => {
+                self.expression(value)?;
+                self.span = sym.span();
+                match self.resolve(&sym.name, slot.get())? {
+                    Variable::Local(slot) => self.emit_op_u8(OpCode::SetLocal, slot),
+                    Variable::Upvalue(index) => self.emit_op_u8(OpCode::SetUpvalue, index),
+                    Variable::Global(name) => self.emit_op_u16(OpCode::SetGlobal, name),
+                }
+            }
+            Expr::Get(object, name) => {
+                self.expression(object)?;
+                self.span = name.span();
+                let name = self.identifier_constant(&name.name)?;
+                self.emit_op_u16(OpCode::GetProperty, name);
+            }
+            Expr::Set(object, name, value) => {
+                self.expression(object)?;
+                self.expression(value)?;
+                self.span = name.span();
+                let name = self.identifier_constant(&name.name)?;
+                self.emit_op_u16(OpCode::SetProperty, name);
+            }
+            Expr::List(elements) => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                if elements.len() > u16::MAX as usize {
+                    return Err(self.error(format!("More than {} list elements", u16::MAX)));
+                }
+                self.emit_op_u16(OpCode::List, elements.len() as u16);
+            }
+            //each entry is added as soon as it is evaluated, so a bad key stops the rest
+            Expr::Map(loc, entries) => {
+                self.emit_op(OpCode::Map);
+                for (key, value) in entries {
+                    self.expression(key)?;
+                    self.expression(value)?;
+                    self.span = loc.span(1);
+                    self.emit_op(OpCode::MapEntry);
+                }
+            }
+            Expr::Index(object, loc, index) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.span = loc.span(1);
+                self.emit_op(OpCode::GetIndex);
+            }
+            Expr::SetIndex(object, loc, index, value) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.expression(value)?;
+                self.span = loc.span(1);
+                self.emit_op(OpCode::SetIndex);
+            }
+        }
+        Ok(())
+    }
+
+    //the condition of an if, loop or '?' is left on the stack for the jump after it
+    fn condition(
+        &mut self,
+        cond: &Expr,
+        loc: &SourceLocation,
+        check: Check,
+        keyword: &str,
+    ) -> Result<(), Error> {
+        self.expression(cond)?;
+        self.span = loc.span(keyword.len());
+        self.emit_op_u8(OpCode::CheckBool, check as u8);
+        Ok(())
+    }
+
+    // variables
+
+    fn get_variable(&mut self, name: &str, slot: Option<Slot>) -> Result<(), Error> {
+        match self.resolve(name, slot)? {
+            Variable::Local(slot) => self.emit_op_u8(OpCode::GetLocal, slot),
+            Variable::Upvalue(index) => self.emit_op_u8(OpCode::GetUpvalue, index),
+            Variable::Global(name) => self.emit_op_u16(OpCode::GetGlobal, name),
+        }
+        if let Some((function, index)) = slot.map(|slot| self.local(slot)) {
+            if self.functions[function].locals[index as usize].unset {
+                let name = self.identifier_constant(name)?;
+                self.emit_op_u16(OpCode::CheckInitialized, name);
+            }
+        }
+        Ok(())
+    }
+
+    //a variable the resolver left without a slot is a global
+    fn resolve(&mut self, name: &str, slot: Option<Slot>) -> Result<Variable, Error> {
+        let Some(slot) = slot else {
+            return Ok(Variable::Global(self.identifier_constant(name)?));
+        };
+        let (function, index) = self.local(slot);
+        let current = self.functions.len() - 1;
+        if function == current {
+            Ok(Variable::Local(index))
+        } else {
+            self.capture(current, function, index)
+                .map(Variable::Upvalue)
+        }
+    }
+
+    //the function a resolved variable is a local of, and its index in the locals
+    fn local(&self, slot: Slot) -> (usize, u8) {
+        let scope = &self.scopes[self.scopes.len() - 1 - slot.depth];
+        //add_local makes sure there are no more locals than fit in a u8
+        (scope.function, (scope.base + slot.index) as u8)
+    }
+
+    //captures a local of an enclosing function in every function in between
+    fn capture(&mut self, function: usize, owner: usize, index: u8) -> Result<u8, Error> {
+        if function - 1 == owner {
+            self.functions[owner].locals[index as usize].captured = true;
+            return self.add_upvalue(function, true, index);
+        }
+        let index = self.capture(function - 1, owner, index)?;
+        self.add_upvalue(function, false, index)
+    }
+
+    fn add_upvalue(&mut self, function: usize, is_local: bool, index: u8) -> Result<u8, Error> {
+        let upvalue = UpvalueRef { is_local, index };
+        let upvalues = &mut self.functions[function].upvalues;
+        if let Some(existing) = upvalues.iter().position(|u| *u == upvalue) {
+            return Ok(existing as u8);
+        }
+        if upvalues.len() == u8::MAX as usize + 1 {
+            return Err(self.error(format!(
+                "More than {} variables captured by one function",
+                u8::MAX as usize + 1
+            )));
+        }
+        upvalues.push(upvalue);
+        Ok((upvalues.len() - 1) as u8)
+    }
+
+    //a global is defined by name, a local is the value already on the stack
+    fn define_variable(&mut self, sym: &Symbol) -> Result<(), Error> {
+        if self.state().scope_depth == 0 {
+            self.define_global(sym)
+        } else {
+            self.add_local(sym)
+        }
+    }
+
+    fn define_global(&mut self, sym: &Symbol) -> Result<(), Error> {
+        self.span = sym.span();
+        let name = self.identifier_constant(&sym.name)?;
+        self.emit_op_u16(OpCode::DefineGlobal, name);
+        Ok(())
+    }
+
+    fn add_local(&mut self, sym: &Symbol) -> Result<(), Error> {
+        let state = self.state_mut();
+        if state.locals.len() == u8::MAX as usize + 1 {
+            return Err(Error {
+                message: format!(
+                    "More than {} local variables in one function",
+                    u8::MAX as usize + 1
+                ),
+                span: sym.span(),
+            });
+        }
+        let depth = state.scope_depth;
+        state.locals.push(Local {
+            depth,
+            captured: false,
+            unset: false,
+        });
+        Ok(())
+    }
+
+    // scopes and loops
+
+    fn begin_scope(&mut self) {
+        let scope = Scope {
+            function: self.functions.len() - 1,
+            base: self.state().locals.len(),
+        };
+        self.scopes.push(scope);
+        self.state_mut().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+        self.state_mut().scope_depth -= 1;
+        let depth = self.state().scope_depth;
+        while let Some(local) = self.state().locals.last() {
+            if local.depth <= depth {
+                break;
+            }
+            let op = if local.captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            self.emit_op(op);
+            self.state_mut().locals.pop();
+        }
+    }
+
+    fn begin_loop(&mut self, continue_target: Option<usize>) {
+        let state = self.state_mut();
+        let lp = Loop {
+            locals: state.locals.len(),
+            handlers: state.handlers,
+            continue_target,
+            continues: vec![],
+            breaks: vec![],
+        };
+        state.loops.push(lp);
+    }
+
+    fn end_loop(&mut self) -> Result<(), Error> {
+        let lp = self.state_mut().loops.pop().expect("a loop was begun");
+        for jump in lp.breaks {
+            self.patch_jump(jump)?;
+        }
+        Ok(())
+    }
+
+    //drops the locals and try statements inside the loop before a break or continue,
+    //the compiler still knows about the locals for the code after it
+    fn leave_loop(&mut self) {
+        let lp = self.innermost_loop();
+        let (locals, handlers) = (lp.locals, lp.handlers);
+        for _ in handlers..self.state().handlers {
+            self.emit_op(OpCode::PopHandler);
+        }
+        for i in (locals..self.state().locals.len()).rev() {
+            let op = if self.state().locals[i].captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            self.emit_op(op);
+        }
+    }
+
+    fn innermost_loop(&mut self) -> &mut Loop {
+        self.state_mut()
+            .loops
+            .last_mut()
+            .expect("parsing makes sure break and continue are in a loop")
+    }
+
+    // emitting bytecode
+
+    fn emit_byte(&mut self, byte: u8) {
+        let span = self.span;
+        self.chunk().write(byte, span);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_op_u8(&mut self, op: OpCode, operand: u8) {
+        self.emit_op(op);
+        self.emit_byte(operand);
+    }
+
+    fn emit_op_u16(&mut self, op: OpCode, operand: u16) {
+        self.emit_op(op);
+        self.emit_byte((operand >> 8) as u8);
+        self.emit_byte(operand as u8);
+    }
+
+    fn emit_constant(&mut self, constant: Constant) -> Result<(), Error> {
+        let index = self.make_constant(constant)?;
+        self.emit_op_u16(OpCode::Constant, index);
+        Ok(())
+    }
+
+    fn make_constant(&mut self, constant: Constant) -> Result<u16, Error> {
+        let index = self.chunk().add_constant(constant);
+        if index > u16::MAX as usize {
+            return Err(self.error(format!(
+                "More than {} constants in one function",
+                u16::MAX as usize + 1
+            )));
+        }
+        Ok(index as u16)
+    }
+
+    fn identifier_constant(&mut self, name: &str) -> Result<u16, Error> {
+        self.make_constant(Constant::String(name.into()))
+    }
+
+    //emits a forward jump with a placeholder offset, returning where to patch it
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        self.chunk().code.len() - 2
+    }
+
+    //points the jump at the next instruction
+    fn patch_jump(&mut self, offset: usize) -> Result<(), Error> {
+        let jump = self.chunk().code.len() - offset - 2;
+        if jump > u16::MAX as usize {
+            return Err(self.error("Too much code to jump over".to_string()));
+        }
+        let code = &mut self.chunk().code;
+        code[offset] = (jump >> 8) as u8;
+        code[offset + 1] = jump as u8;
+        Ok(())
+    }
+
+    fn emit_loop(&mut self, start: usize) -> Result<(), Error> {
+        self.emit_op(OpCode::Loop);
+        let offset = self.chunk().code.len() - start + 2;
+        if offset > u16::MAX as usize {
+            return Err(self.error("Loop body too large".to_string()));
+        }
+        self.emit_byte((offset >> 8) as u8);
+        self.emit_byte(offset as u8);
+        Ok(())
+    }
+
+    // helper functions
+
+    fn state(&self) -> &FunctionState {
+        self.functions.last().expect("there is always a function")
+    }
+
+    fn state_mut(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("there is always a function")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.state_mut().proto.chunk
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error {
+            message,
+            span: self.span,
+        }
+    }
+}
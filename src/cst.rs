@@ -0,0 +1,547 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::parser::exprstmt::{
+    BinOpType, BinaryOp, ClassDecl, Expr, FunctionDecl, Literal, LogicOpType, LogicalOp,
+    SourceLocation, Stmt, Symbol, UnaryOp, UniOpType,
+};
+use crate::scanner::token::{self, Token, TokenType, TriviaKind};
+
+//the concrete syntax tree, every token the parser saw is in it in source order,
+//with its trivia, so the text of the tree is exactly the source it was parsed from
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Program,
+
+    // Statements.
+    VarDecl,
+    FuncDecl,
+    ClassDecl,
+    Method,
+    ParamList,
+    Block,
+    ExprStmt,
+    PrintStmt,
+    IfStmt,
+    WhileStmt,
+    ForStmt,
+    BreakStmt,
+    ContinueStmt,
+    ReturnStmt,
+    ThrowStmt,
+    TryStmt,
+
+    // Expressions.
+    Literal,
+    Variable,
+    This,
+    Grouping,
+    Unary,
+    Binary,
+    Logical,
+    Ternary,
+    Call,
+    Get,
+    Index,
+    // Any assignment, the target decides which.
+    Assign,
+    List,
+    Map,
+    MapEntry,
+
+    // A declaration with a syntax error and the tokens skipped after it.
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub enum Element {
+    Node(Node),
+    Token(Token),
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub children: Vec<Element>,
+}
+
+impl Node {
+    //the source text of the node, trivia included
+    #[cfg(test)]
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        for token in self.all_tokens() {
+            for trivia in token.leading.iter() {
+                out += &trivia.text;
+            }
+            out += &String::from_utf8_lossy(&token.lexeme);
+        }
+        out
+    }
+
+    //the child nodes, without the tokens between them
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Node(node) => Some(node),
+            Element::Token(_) => None,
+        })
+    }
+
+    //the tokens that are direct children
+    pub fn tokens(&self) -> impl Iterator<Item = &Token> {
+        self.children.iter().filter_map(|child| match child {
+            Element::Token(token) => Some(token),
+            Element::Node(_) => None,
+        })
+    }
+
+    //every token in the node, in source order
+    pub fn all_tokens(&self) -> Vec<&Token> {
+        let mut tokens = vec![];
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a Token>) {
+        for child in &self.children {
+            match child {
+                Element::Node(node) => node.collect_tokens(tokens),
+                Element::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    fn token(&self, token_type: TokenType) -> &Token {
+        self.tokens()
+            .find(|t| t.token_type == token_type)
+            .expect("the parser checked the token is there")
+    }
+
+    fn node(&self, kind: NodeKind) -> &Node {
+        self.nodes()
+            .find(|n| n.kind == kind)
+            .expect("the parser checked the node is there")
+    }
+
+    fn first_node(&self) -> &Node {
+        self.nodes()
+            .next()
+            .expect("the parser checked the node is there")
+    }
+}
+
+//builds the tree while the parser runs, nodes are opened before their first token
+//and closed after their last, or opened later at a checkpoint to wrap what came before
+#[derive(Default)]
+pub struct Builder {
+    //the kinds of the open nodes and where their children start
+    open: Vec<(NodeKind, usize)>,
+    children: Vec<Element>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint(usize);
+
+impl Builder {
+    pub fn start_node(&mut self, kind: NodeKind) {
+        self.open.push((kind, self.children.len()));
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.children.len())
+    }
+
+    //opens a node that starts with everything added since the checkpoint
+    pub fn start_node_at(&mut self, checkpoint: Checkpoint, kind: NodeKind) {
+        self.open.push((kind, checkpoint.0));
+    }
+
+    pub fn finish_node(&mut self) {
+        let (kind, start) = self.open.pop().expect("a node is open");
+        let children = self.children.split_off(start);
+        self.children.push(Element::Node(Node { kind, children }));
+    }
+
+    //closes the nodes an error left open, down to the given depth
+    pub fn finish_nodes_to(&mut self, depth: usize) {
+        while self.open.len() > depth {
+            self.finish_node();
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.open.len()
+    }
+
+    pub fn token(&mut self, token: Token) {
+        self.children.push(Element::Token(token));
+    }
+
+    //the kind of the node that was closed last, if nothing has been added after it
+    pub fn last_node_kind(&self) -> Option<NodeKind> {
+        match self.children.last() {
+            Some(Element::Node(node)) => Some(node.kind),
+            _ => None,
+        }
+    }
+
+    pub fn finish(mut self) -> Node {
+        assert!(self.open.is_empty(), "every node is closed");
+        match self.children.pop() {
+            Some(Element::Node(root)) if self.children.is_empty() => root,
+            _ => panic!("the tree has one root"),
+        }
+    }
+}
+
+//an indented tree of the nodes and tokens, each token after the trivia in front of it
+pub fn dump(node: &Node) -> String {
+    let mut out = String::new();
+    dump_node(node, 0, &mut out);
+    out
+}
+
+fn dump_node(node: &Node, depth: usize, out: &mut String) {
+    *out += &format!("{}{:?}\n", "  ".repeat(depth), node.kind);
+    let indent = "  ".repeat(depth + 1);
+    for child in &node.children {
+        match child {
+            Element::Node(node) => dump_node(node, depth + 1, out),
+            Element::Token(token) => {
+                for trivia in token.leading.iter() {
+                    let kind = match trivia.kind {
+                        TriviaKind::Whitespace => "whitespace",
+                        TriviaKind::Newline => "newline",
+                        TriviaKind::Comment => "comment",
+                    };
+                    *out += &format!("{indent}({kind} {:?})\n", trivia.text);
+                }
+                *out += &format!(
+                    "{indent}{:?} {:?} @{}:{}\n",
+                    token.token_type,
+                    String::from_utf8_lossy(&token.lexeme),
+                    token.line,
+                    token.column
+                );
+            }
+        }
+    }
+}
+
+// -----------------
+// Lowering to the AST
+// -----------------
+
+//derives the abstract syntax tree, Error nodes are left out
+pub fn lower(program: &Node) -> Vec<Stmt> {
+    lower_stmts(program)
+}
+
+fn lower_stmts(node: &Node) -> Vec<Stmt> {
+    node.nodes()
+        .filter(|n| n.kind != NodeKind::Error)
+        .map(lower_stmt)
+        .collect()
+}
+
+fn lower_stmt(node: &Node) -> Stmt {
+    match node.kind {
+        NodeKind::VarDecl => Stmt::VarDeclaration(
+            symbol(node.token(TokenType::Identifier)),
+            node.nodes().next().map(lower_expr),
+        ),
+        NodeKind::FuncDecl => Stmt::Function(Rc::new(lower_function(node))),
+        NodeKind::ClassDecl => Stmt::Class(Rc::new(ClassDecl {
+            name: symbol(node.token(TokenType::Identifier)),
+            methods: node
+                .nodes()
+                .map(|method| Rc::new(lower_function(method)))
+                .collect(),
+        })),
+        NodeKind::Block => Stmt::Block(lower_stmts(node)),
+        NodeKind::ExprStmt => Stmt::Expression(lower_expr(node.first_node())),
+        NodeKind::PrintStmt => Stmt::Print(lower_expr(node.first_node())),
+        NodeKind::IfStmt => {
+            let mut nodes = node.nodes();
+            let cond = lower_expr(nodes.next().unwrap());
+            let then_branch = Box::new(lower_stmt(nodes.next().unwrap()));
+            let else_branch = nodes.next().map(|n| Box::new(lower_stmt(n)));
+            Stmt::If(
+                location(node.token(TokenType::If)),
+                cond,
+                then_branch,
+                else_branch,
+            )
+        }
+        NodeKind::WhileStmt => {
+            let mut nodes = node.nodes();
+            let cond = lower_expr(nodes.next().unwrap());
+            let body = Box::new(lower_stmt(nodes.next().unwrap()));
+            Stmt::While(location(node.token(TokenType::While)), cond, body)
+        }
+        NodeKind::ForStmt => lower_for(node),
+        NodeKind::BreakStmt => Stmt::Break,
+        NodeKind::ContinueStmt => Stmt::Continue,
+        NodeKind::ReturnStmt => Stmt::Return(node.nodes().next().map(lower_expr)),
+        NodeKind::ThrowStmt => Stmt::Throw(
+            location(node.token(TokenType::Throw)),
+            lower_expr(node.first_node()),
+        ),
+        NodeKind::TryStmt => {
+            let mut blocks = node.nodes();
+            let body = lower_stmts(blocks.next().unwrap());
+            let handler = lower_stmts(blocks.next().unwrap());
+            Stmt::Try(body, symbol(node.token(TokenType::Identifier)), handler)
+        }
+        kind => unreachable!("{kind:?} is not a statement"),
+    }
+}
+
+//which part of the header a node is in depends on the ';' and ')' before it
+fn lower_for(node: &Node) -> Stmt {
+    let (mut init, mut cond, mut increment, mut body) = (None, None, None, None);
+    //0 is the initializer, 1 the condition, 2 the increment and 3 the body
+    let mut part = 0;
+    for child in &node.children {
+        match child {
+            Element::Token(t) if t.token_type == TokenType::Semicolon => part += 1,
+            Element::Token(t) if t.token_type == TokenType::RightParen => part = 3,
+            Element::Token(_) => (),
+            //the initializer has its own ';'
+            Element::Node(n) => match part {
+                0 => {
+                    init = Some(Box::new(lower_stmt(n)));
+                    part = 1;
+                }
+                1 => cond = Some(lower_expr(n)),
+                2 => increment = Some(lower_expr(n)),
+                _ => body = Some(Box::new(lower_stmt(n))),
+            },
+        }
+    }
+    Stmt::For(
+        location(node.token(TokenType::For)),
+        init,
+        cond,
+        increment,
+        body.expect("the parser checked the body is there"),
+    )
+}
+
+//a function declaration or a method, both have a name, parameters and a body
+fn lower_function(node: &Node) -> FunctionDecl {
+    FunctionDecl {
+        name: symbol(node.token(TokenType::Identifier)),
+        params: node
+            .node(NodeKind::ParamList)
+            .tokens()
+            .filter(|t| t.token_type == TokenType::Identifier)
+            .map(symbol)
+            .collect(),
+        body: lower_stmts(node.node(NodeKind::Block)),
+    }
+}
+
+fn lower_expr(node: &Node) -> Expr {
+    match node.kind {
+        NodeKind::Literal => {
+            let token = node.tokens().next().unwrap();
+            Expr::Literal(match (&token.token_type, &token.literal) {
+                (TokenType::Number, Some(token::Literal::Num(n))) => Literal::Number(*n),
+                (TokenType::String, Some(token::Literal::Str(s))) => Literal::String(s.clone()),
+                (TokenType::True, _) => Literal::True,
+                (TokenType::False, _) => Literal::False,
+                (TokenType::Null, _) => Literal::Null,
+                (t, l) => unreachable!("{t:?} with {l:?} is not a literal"),
+            })
+        }
+        NodeKind::Variable => {
+            Expr::Variable(symbol(node.token(TokenType::Identifier)), Cell::new(None))
+        }
+        NodeKind::This => Expr::This(location(node.token(TokenType::This)), Cell::new(None)),
+        NodeKind::Grouping => Expr::Grouping(Box::new(lower_expr(node.first_node()))),
+        NodeKind::Unary => Expr::Unary(
+            unary_op(node.tokens().next().unwrap()),
+            Box::new(lower_expr(node.first_node())),
+        ),
+        NodeKind::Binary => {
+            let (left, right) = two(node);
+            Expr::Binary(left, binary_op(node.tokens().next().unwrap()), right)
+        }
+        NodeKind::Logical => {
+            let (left, right) = two(node);
+            Expr::Logical(left, logical_op(node.tokens().next().unwrap()), right)
+        }
+        NodeKind::Ternary => {
+            let mut nodes = node.nodes().map(|n| Box::new(lower_expr(n)));
+            let cond = nodes.next().unwrap();
+            let then_branch = nodes.next().unwrap();
+            let else_branch = nodes.next().unwrap();
+            Expr::Ternary(
+                cond,
+                location(node.token(TokenType::QuestionMark)),
+                then_branch,
+                else_branch,
+            )
+        }
+        NodeKind::Call => {
+            let mut nodes = node.nodes().map(lower_expr);
+            let callee = Box::new(nodes.next().unwrap());
+            Expr::Call(
+                callee,
+                location(node.token(TokenType::LeftParen)),
+                nodes.collect(),
+            )
+        }
+        NodeKind::Get => Expr::Get(
+            Box::new(lower_expr(node.first_node())),
+            symbol(node.token(TokenType::Identifier)),
+        ),
+        NodeKind::Index => {
+            let (object, index) = two(node);
+            Expr::Index(object, location(node.token(TokenType::LeftBracket)), index)
+        }
+        NodeKind::Assign => lower_assign(node),
+        NodeKind::List => Expr::List(node.nodes().map(lower_expr).collect()),
+        NodeKind::Map => Expr::Map(
+            location(node.token(TokenType::LeftCurly)),
+            node.nodes()
+                .map(|entry| {
+                    let (key, value) = two(entry);
+                    (*key, *value)
+                })
+                .collect(),
+        ),
+        kind => unreachable!("{kind:?} is not an expression"),
+    }
+}
+
+//the parser only lets variables, properties and indexes be assigned to
+fn lower_assign(node: &Node) -> Expr {
+    let mut nodes = node.nodes();
+    let target = nodes.next().unwrap();
+    let value = Box::new(lower_expr(nodes.next().unwrap()));
+    match target.kind {
+        NodeKind::Variable => Expr::Assignment(
+            symbol(target.token(TokenType::Identifier)),
+            value,
+            Cell::new(None),
+        ),
+        NodeKind::Get => Expr::Set(
+            Box::new(lower_expr(target.first_node())),
+            symbol(target.token(TokenType::Identifier)),
+            value,
+        ),
+        NodeKind::Index => {
+            let (object, index) = two(target);
+            let location = location(target.token(TokenType::LeftBracket));
+            Expr::SetIndex(object, location, index, value)
+        }
+        kind => unreachable!("{kind:?} can't be assigned to"),
+    }
+}
+
+// helper functions
+
+//the first two child nodes as expressions
+fn two(node: &Node) -> (Box<Expr>, Box<Expr>) {
+    let mut nodes = node.nodes();
+    let first = Box::new(lower_expr(nodes.next().unwrap()));
+    let second = Box::new(lower_expr(nodes.next().unwrap()));
+    (first, second)
+}
+
+fn symbol(token: &Token) -> Symbol {
+    Symbol {
+        name: String::from_utf8(token.lexeme.clone()).unwrap(),
+        line: token.line,
+        column: token.column,
+    }
+}
+
+fn location(token: &Token) -> SourceLocation {
+    SourceLocation {
+        line: token.line,
+        col: token.column,
+    }
+}
+
+fn binary_op(op: &Token) -> BinaryOp {
+    let b_type = match op.token_type {
+        TokenType::EqualEqual => BinOpType::EqualEqual,
+        TokenType::BangEqual => BinOpType::NotEqual,
+        TokenType::Less => BinOpType::Less,
+        TokenType::LessEqual => BinOpType::LessEqual,
+        TokenType::Greater => BinOpType::Greater,
+        TokenType::GreaterEqual => BinOpType::GreaterEqual,
+        TokenType::Plus => BinOpType::Add,
+        TokenType::Minus => BinOpType::Sub,
+        TokenType::Star => BinOpType::Mult,
+        TokenType::Slash => BinOpType::Div,
+        t => unreachable!("{t:?} is not a binary operator"),
+    };
+    BinaryOp {
+        b_type,
+        line: op.line,
+        column: op.column,
+    }
+}
+
+fn logical_op(op: &Token) -> LogicalOp {
+    let l_type = match op.token_type {
+        TokenType::And => LogicOpType::And,
+        _ => LogicOpType::Or,
+    };
+    LogicalOp {
+        l_type,
+        line: op.line,
+        column: op.column,
+    }
+}
+
+fn unary_op(op: &Token) -> UnaryOp {
+    let u_type = match op.token_type {
+        TokenType::Bang => UniOpType::Bang,
+        _ => UniOpType::Minus,
+    };
+    UnaryOp {
+        u_type,
+        line: op.line,
+        column: op.column,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parser, scanner};
+
+    //the tree keeps every byte of the source, whether or not it scanned and parsed
+    #[test]
+    fn text_is_the_source() {
+        let sources = [
+            "",
+            "print 1;",
+            "// only a comment",
+            "  var x = 1; // trailing\n\n/* block\n comment */ print x;\n",
+            "func f(a, b) {\r\n\treturn a + b;\r\n}\r\nprint f(1, 2);",
+            "class A { init() { this.x = [1, {\"k\": 2}]; } }",
+            "var é = \"ünïcödé 😀\"; print é;",
+            //scan errors
+            "print \"unterminated;",
+            "var x = 1 @ 2;\nprint x;",
+            "/* never closed",
+            //parse errors
+            "var = 2;\nprint 3",
+            "func f( { return 1; }\nprint f;",
+            "if (true { print 1; } else }",
+            "print (1 + ;\n}}}\nvar y;",
+            "class { x() {} }",
+        ];
+        for source in sources {
+            let (tokens, _) = scanner::scan(source.to_string());
+            let (cst, _) = parser::parse_cst(tokens);
+            assert_eq!(cst.text(), source);
+        }
+    }
+}
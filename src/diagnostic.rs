@@ -0,0 +1,249 @@
+use std::fmt;
+
+use crate::utils::json::Json;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+//a range of characters on one line, lines and columns start at 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: i64,
+    pub len: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: i64, len: usize) -> Span {
+        Span { line, column, len }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object([
+            ("line", self.line.into()),
+            ("column", self.column.into()),
+            ("length", self.len.into()),
+        ])
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+//an error or warning from any phase, the scanner, parser and interpreter
+//all convert their own errors into this so they are reported the same way
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    //the same information as render, for tools, without the source lines
+    pub fn to_json(&self, file_name: &str) -> Json {
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| {
+                Json::object([
+                    ("span", label.span.to_json()),
+                    ("message", label.message.as_str().into()),
+                ])
+            })
+            .collect();
+        Json::object([
+            ("severity", self.severity.to_string().into()),
+            ("code", self.code.into()),
+            ("message", self.message.as_str().into()),
+            ("file", file_name.into()),
+            ("span", self.span.to_json()),
+            ("labels", Json::Array(labels)),
+            ("notes", self.notes.clone().into()),
+        ])
+    }
+
+    //renders the diagnostic rustc-style, with the offending source lines underlined
+    pub fn render(&self, source: &str, file_name: &str) -> String {
+        let lines: Vec<&str> = source.lines().collect();
+
+        //the primary span is underlined with '^', labels with '-'
+        let mut marks: Vec<(Span, char, &str)> = vec![(self.span, '^', "")];
+        for label in &self.labels {
+            if label.span == self.span {
+                marks[0].2 = label.message.as_str();
+            } else {
+                marks.push((label.span, '-', label.message.as_str()));
+            }
+        }
+        marks.sort_by_key(|(span, _, _)| (span.line, span.column));
+
+        let gutter = marks
+            .iter()
+            .map(|(span, _, _)| span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad = " ".repeat(gutter);
+
+        let mut out = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+        out += &format!(
+            "{pad}--> {}:{}:{}\n",
+            file_name, self.span.line, self.span.column
+        );
+        out += &format!("{pad} |\n");
+
+        let mut last_line = 0;
+        for (span, mark, message) in marks {
+            let text = match lines.get(span.line.wrapping_sub(1)) {
+                Some(text) => *text,
+                None => "",
+            };
+            if span.line != last_line {
+                out += &format!("{:>gutter$} | {}\n", span.line, text.replace('\t', TAB));
+                last_line = span.line;
+            }
+
+            //the span may run past the end of the line, e.g. multi-line strings
+            let start = (span.column.max(1) - 1) as usize;
+            let line_len = text.chars().count();
+            let len = span.len.min(line_len.saturating_sub(start)).max(1);
+            //the marks line up with what the terminal shows, not with the characters
+            let indent =
+                text.chars().take(start).map(width).sum::<usize>() + start.saturating_sub(line_len);
+            let marked = text.chars().skip(start).take(len).map(width).sum::<usize>();
+            let indent = " ".repeat(indent);
+            let underline = mark.to_string().repeat(marked.max(1));
+            if message.is_empty() {
+                out += &format!("{pad} | {indent}{underline}\n");
+            } else {
+                out += &format!("{pad} | {indent}{underline} {message}\n");
+            }
+        }
+
+        for note in &self.notes {
+            out += &format!("{pad} = note: {note}\n");
+        }
+        out
+    }
+}
+
+//tabs in the source are shown as this, so the marks under them line up
+const TAB: &str = "    ";
+
+//the columns a character takes up in a terminal, wide for east asian scripts and emoji,
+//nothing for combining marks, like the wcwidth of most terminals
+fn width(c: char) -> usize {
+    match c {
+        '\t' => TAB.len(),
+        '\u{0300}'..='\u{036F}' | '\u{200B}'..='\u{200F}' | '\u{FE00}'..='\u{FE0F}' => 0,
+        '\u{1100}'..='\u{115F}'
+        | '\u{2E80}'..='\u{303E}'
+        | '\u{3041}'..='\u{33FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{A000}'..='\u{A4CF}'
+        | '\u{AC00}'..='\u{D7A3}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FE30}'..='\u{FE4F}'
+        | '\u{FF00}'..='\u{FF60}'
+        | '\u{FFE0}'..='\u{FFE6}'
+        | '\u{1F300}'..='\u{1F64F}'
+        | '\u{1F900}'..='\u{1F9FF}'
+        | '\u{20000}'..='\u{2FFFD}'
+        | '\u{30000}'..='\u{3FFFD}' => 2,
+        _ => 1,
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[line: {}, column: {}] {}[{}]: {}",
+            self.span.line, self.span.column, self.severity, self.code, self.message
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Diagnostic, Span};
+
+    //the marks are under the same text in the terminal as the span is in the source
+    #[test]
+    fn marks_line_up_with_tabs_and_wide_characters() {
+        let source = "\tvar 名前 = \"漢字\" + 1;";
+        let diagnostic = Diagnostic::error("E0201", "bad", Span::new(1, 16, 1))
+            .with_label(Span::new(1, 6, 2), "declared here");
+        assert_eq!(
+            diagnostic.render(source, "main.aprn"),
+            "error[E0201]: bad\n \
+             --> main.aprn:1:16\n  \
+             |\n\
+             1 |     var 名前 = \"漢字\" + 1;\n  \
+             |         ---- declared here\n  \
+             |                       ^\n"
+        );
+    }
+
+    //the order of the fields is fixed, spans count characters
+    #[test]
+    fn to_json() {
+        let diagnostic = Diagnostic::error("E0102", "Expected ';'", Span::new(2, 9, 1))
+            .with_label(Span::new(2, 9, 1), "found 'π'")
+            .with_note("a \"note\"\twith\u{1}escapes");
+        assert_eq!(
+            diagnostic.to_json("dir/main.aprn").to_string(),
+            concat!(
+                r#"{"severity":"error","code":"E0102","message":"Expected ';'","file":"dir/main.aprn","#,
+                r#""span":{"line":2,"column":9,"length":1},"#,
+                r#""labels":[{"span":{"line":2,"column":9,"length":1},"message":"found 'π'"}],"#,
+                r#""notes":["a \"note\"\twith\u0001escapes"]}"#
+            )
+        );
+    }
+}
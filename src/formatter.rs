@@ -0,0 +1,544 @@
+use crate::parser::exprstmt::{Expr, FunctionDecl, Literal, Stmt};
+use crate::scanner::token::{Token, TokenType, Trivia, TriviaKind};
+
+const INDENT: &str = "    ";
+
+//prints the program in the canonical style, the tokens it was parsed from
+//give the comments and blank lines to keep and the exact text of every literal,
+//the program has to have parsed without errors
+pub fn format(stmts: &[Stmt], tokens: Vec<Token>) -> String {
+    let mut f = Formatter {
+        tokens,
+        current: 0,
+        out: String::new(),
+        indent: 0,
+        pending: 0,
+        continuation: false,
+    };
+    for stmt in stmts {
+        f.stmt(stmt);
+    }
+    f.comments_before(false);
+
+    let mut out = f.out.trim_end().to_string();
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+struct Formatter {
+    tokens: Vec<Token>,
+    current: usize,
+    out: String,
+    indent: usize,
+    //line breaks waiting to be written before the next text, 2 leaves a blank line
+    pending: usize,
+    //true when a comment split a statement, the rest of it is indented one more level
+    continuation: bool,
+}
+
+impl Formatter {
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(e) => {
+                self.expr(e);
+                self.token(TokenType::Semicolon);
+            }
+            Stmt::Print(e) => {
+                self.keyword(TokenType::Print);
+                self.expr(e);
+                self.token(TokenType::Semicolon);
+            }
+            Stmt::VarDeclaration(_, init) => self.var_declaration(init),
+            Stmt::Block(stmts) => self.block(stmts),
+            Stmt::If(_, cond, then_branch, else_branch) => {
+                self.keyword(TokenType::If);
+                self.condition(cond);
+                self.body(then_branch);
+                if let Some(else_branch) = else_branch {
+                    //else goes after the closing brace, or on its own line
+                    if matches!(**then_branch, Stmt::Block(_)) {
+                        self.after_brace();
+                    }
+                    self.token(TokenType::Else);
+                    match **else_branch {
+                        Stmt::If(..) => {
+                            self.space();
+                            self.stmt(else_branch);
+                        }
+                        _ => self.body(else_branch),
+                    }
+                }
+            }
+            Stmt::While(_, cond, body) => {
+                self.keyword(TokenType::While);
+                self.condition(cond);
+                self.body(body);
+            }
+            Stmt::For(_, init, cond, increment, body) => {
+                self.keyword(TokenType::For);
+                self.token(TokenType::LeftParen);
+                match init.as_deref() {
+                    Some(Stmt::VarDeclaration(_, init)) => self.var_declaration(init),
+                    Some(Stmt::Expression(e)) => {
+                        self.expr(e);
+                        self.token(TokenType::Semicolon);
+                    }
+                    _ => self.token(TokenType::Semicolon),
+                }
+                if let Some(cond) = cond {
+                    self.space();
+                    self.expr(cond);
+                }
+                self.token(TokenType::Semicolon);
+                if let Some(increment) = increment {
+                    self.space();
+                    self.expr(increment);
+                }
+                self.token(TokenType::RightParen);
+                self.body(body);
+            }
+            Stmt::Break => {
+                self.token(TokenType::Break);
+                self.token(TokenType::Semicolon);
+            }
+            Stmt::Continue => {
+                self.token(TokenType::Continue);
+                self.token(TokenType::Semicolon);
+            }
+            Stmt::Function(decl) => {
+                self.keyword(TokenType::Func);
+                self.function(decl);
+            }
+            Stmt::Return(e) => {
+                self.token(TokenType::Return);
+                if let Some(e) = e {
+                    self.space();
+                    self.expr(e);
+                }
+                self.token(TokenType::Semicolon);
+            }
+            Stmt::Class(decl) => {
+                self.keyword(TokenType::Class);
+                self.token(TokenType::Identifier);
+                self.space();
+                self.braced(|f| {
+                    for method in &decl.methods {
+                        f.function(method);
+                        f.line();
+                    }
+                });
+            }
+            Stmt::Throw(_, e) => {
+                self.keyword(TokenType::Throw);
+                self.expr(e);
+                self.token(TokenType::Semicolon);
+            }
+            Stmt::Try(body, _, handler) => {
+                self.keyword(TokenType::Try);
+                self.block(body);
+                self.after_brace();
+                self.keyword(TokenType::Catch);
+                self.token(TokenType::LeftParen);
+                self.token(TokenType::Identifier);
+                self.token(TokenType::RightParen);
+                self.space();
+                self.block(handler);
+            }
+        }
+        self.line();
+    }
+
+    fn var_declaration(&mut self, init: &Option<Expr>) {
+        self.keyword(TokenType::Var);
+        self.token(TokenType::Identifier);
+        if let Some(init) = init {
+            self.space();
+            self.token(TokenType::Equal);
+            self.space();
+            self.expr(init);
+        }
+        self.token(TokenType::Semicolon);
+    }
+
+    // the "(cond)" after if and while
+    fn condition(&mut self, cond: &Expr) {
+        self.token(TokenType::LeftParen);
+        self.expr(cond);
+        self.token(TokenType::RightParen);
+    }
+
+    //a block body stays on the line of its statement, any other goes on the next line
+    fn body(&mut self, body: &Stmt) {
+        match body {
+            Stmt::Block(stmts) => {
+                self.space();
+                self.block(stmts);
+            }
+            _ => {
+                self.indent += 1;
+                self.line();
+                self.stmt(body);
+                self.indent -= 1;
+            }
+        }
+    }
+
+    fn function(&mut self, decl: &FunctionDecl) {
+        self.token(TokenType::Identifier);
+        self.token(TokenType::LeftParen);
+        for i in 0..decl.params.len() {
+            if i > 0 {
+                self.comma();
+            }
+            self.token(TokenType::Identifier);
+        }
+        self.token(TokenType::RightParen);
+        self.space();
+        self.block(&decl.body);
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.braced(|f| {
+            for stmt in stmts {
+                f.stmt(stmt);
+            }
+        });
+    }
+
+    //else and catch go on the line of the closing brace before them,
+    //unless there are comments in between, then they start a line of their own
+    fn after_brace(&mut self) {
+        if self.has_comments() {
+            self.line();
+        } else {
+            self.space();
+        }
+    }
+
+    //the contents go on their own indented lines, an empty pair stays as {},
+    //comments before the opening brace are moved after it so it stays on the line
+    fn braced(&mut self, contents: impl FnOnce(&mut Formatter)) {
+        let moved = std::mem::take(&mut self.tokens[self.current].leading);
+        self.token(TokenType::LeftCurly);
+        let moved_comments = moved.iter().any(|t| t.kind == TriviaKind::Comment);
+        if self.peek().token_type == TokenType::RightCurly
+            && !moved_comments
+            && !self.has_comments()
+        {
+            self.token(TokenType::RightCurly);
+            return;
+        }
+        self.indent += 1;
+        self.line();
+        self.trivia(&moved, false);
+        contents(self);
+        self.comments_before(false);
+        self.indent -= 1;
+        self.line();
+        self.token(TokenType::RightCurly);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(lit) => self.token(match lit {
+                Literal::Number(_) => TokenType::Number,
+                Literal::String(_) => TokenType::String,
+                Literal::True => TokenType::True,
+                Literal::False => TokenType::False,
+                Literal::Null => TokenType::Null,
+            }),
+            Expr::Grouping(e) => {
+                self.token(TokenType::LeftParen);
+                self.expr(e);
+                self.token(TokenType::RightParen);
+            }
+            Expr::Unary(_, e) => {
+                self.next();
+                self.expr(e);
+            }
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+                self.expr(left);
+                self.space();
+                self.next();
+                self.space();
+                self.expr(right);
+            }
+            Expr::Ternary(cond, _, then_branch, else_branch) => {
+                self.expr(cond);
+                self.space();
+                self.token(TokenType::QuestionMark);
+                self.space();
+                self.expr(then_branch);
+                self.space();
+                self.token(TokenType::Colon);
+                self.space();
+                self.expr(else_branch);
+            }
+            Expr::Call(callee, _, args) => {
+                self.expr(callee);
+                self.token(TokenType::LeftParen);
+                self.list(args);
+                self.token(TokenType::RightParen);
+            }
+            Expr::Get(object, _) => {
+                self.expr(object);
+                self.token(TokenType::Dot);
+                self.token(TokenType::Identifier);
+            }
+            Expr::Set(object, _, value) => {
+                self.expr(object);
+                self.token(TokenType::Dot);
+                self.token(TokenType::Identifier);
+                self.assign(value);
+            }
+            Expr::List(elements) => {
+                self.token(TokenType::LeftBracket);
+                self.list(elements);
+                self.token(TokenType::RightBracket);
+            }
+            Expr::Map(_, entries) => {
+                self.token(TokenType::LeftCurly);
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.comma();
+                    }
+                    self.expr(key);
+                    self.token(TokenType::Colon);
+                    self.space();
+                    self.expr(value);
+                }
+                self.token(TokenType::RightCurly);
+            }
+            Expr::Index(object, _, index) => {
+                self.expr(object);
+                self.token(TokenType::LeftBracket);
+                self.expr(index);
+                self.token(TokenType::RightBracket);
+            }
+            Expr::SetIndex(object, _, index, value) => {
+                self.expr(object);
+                self.token(TokenType::LeftBracket);
+                self.expr(index);
+                self.token(TokenType::RightBracket);
+                self.assign(value);
+            }
+            Expr::This(..) => self.token(TokenType::This),
+            Expr::Variable(..) => self.token(TokenType::Identifier),
+            Expr::Assignment(_, value, _) => {
+                self.token(TokenType::Identifier);
+                self.assign(value);
+            }
+        }
+    }
+
+    // the " = value" of an assignment
+    fn assign(&mut self, value: &Expr) {
+        self.space();
+        self.token(TokenType::Equal);
+        self.space();
+        self.expr(value);
+    }
+
+    fn list(&mut self, exprs: &[Expr]) {
+        for (i, e) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.comma();
+            }
+            self.expr(e);
+        }
+    }
+
+    // tokens
+
+    //writes the next token, which the tree says has to be of the given type
+    fn token(&mut self, token_type: TokenType) {
+        debug_assert_eq!(self.peek().token_type, token_type);
+        self.next();
+    }
+
+    //a keyword and the space after it
+    fn keyword(&mut self, token_type: TokenType) {
+        self.token(token_type);
+        self.space();
+    }
+
+    fn comma(&mut self) {
+        self.token(TokenType::Comma);
+        self.space();
+    }
+
+    //writes the next token with the comments in front of it
+    fn next(&mut self) {
+        let leading = std::mem::take(&mut self.tokens[self.current].leading);
+        self.trivia(&leading, true);
+        let token = &self.tokens[self.current];
+        let lexeme = String::from_utf8_lossy(&token.lexeme).into_owned();
+        self.current += 1;
+        self.text(&lexeme);
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    fn has_comments(&self) -> bool {
+        self.peek()
+            .leading
+            .iter()
+            .any(|t| t.kind == TriviaKind::Comment)
+    }
+
+    //writes the comments in front of the next token at the current indentation,
+    //used before a closing brace and the end of the file
+    fn comments_before(&mut self, blank_lines: bool) {
+        let leading = std::mem::take(&mut self.tokens[self.current].leading);
+        self.trivia(&leading, blank_lines);
+    }
+
+    //a comment after code stays at the end of its line, any other goes on its own line,
+    //runs of blank lines between statements and comments become one
+    fn trivia(&mut self, trivia: &[Trivia], blank_lines: bool) {
+        //the line breaks since the previous token or comment
+        let mut newlines = 0;
+        for t in trivia {
+            match t.kind {
+                TriviaKind::Whitespace => (),
+                TriviaKind::Newline => newlines += 1,
+                TriviaKind::Comment if newlines == 0 && !self.out.is_empty() => {
+                    let mid_statement = self.pending == 0;
+                    self.out.truncate(self.out.trim_end().len());
+                    self.out.push(' ');
+                    self.out.push_str(t.text.trim_end());
+                    if mid_statement {
+                        self.pending = 1;
+                        self.continuation = true;
+                    }
+                }
+                TriviaKind::Comment => {
+                    if self.pending == 0 && !self.out.is_empty() {
+                        self.pending = 1;
+                        self.continuation = true;
+                    }
+                    if newlines >= 2 {
+                        self.blank_line();
+                    }
+                    self.text(t.text.trim_end());
+                    self.pending = 1;
+                    newlines = 0;
+                }
+            }
+        }
+        if newlines >= 2 && blank_lines {
+            self.blank_line();
+        }
+    }
+
+    // output
+
+    //writes the text after any pending line breaks and indentation
+    fn text(&mut self, text: &str) {
+        if self.pending > 0 {
+            self.out.truncate(self.out.trim_end_matches(' ').len());
+            if !self.out.is_empty() {
+                self.out.push_str(&"\n".repeat(self.pending));
+            }
+            let depth = self.indent + self.continuation as usize;
+            self.out.push_str(&INDENT.repeat(depth));
+            self.pending = 0;
+        }
+        self.out.push_str(text);
+    }
+
+    fn space(&mut self) {
+        if self.pending == 0 && !self.out.ends_with(' ') {
+            self.out.push(' ');
+        }
+    }
+
+    //ends the line, the next statement starts on a new one
+    fn line(&mut self) {
+        self.pending = self.pending.max(1);
+        self.continuation = false;
+    }
+
+    //only between lines, and not straight after an opening brace
+    fn blank_line(&mut self) {
+        if self.pending > 0 && !self.out.trim_end().ends_with('{') {
+            self.pending = 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cst, parser, scanner};
+
+    fn format(source: &str) -> String {
+        let (tokens, errors) = scanner::scan(source.to_string());
+        assert!(errors.is_empty(), "{source}");
+        let (cst, errors) = parser::parse_cst(tokens);
+        assert!(errors.is_empty(), "{source}");
+        let tokens = cst.all_tokens().into_iter().cloned().collect();
+        super::format(&cst::lower(&cst), tokens)
+    }
+
+    //the comments of the source in order, without their indentation
+    fn comments(source: &str) -> Vec<&str> {
+        source
+            .lines()
+            .filter_map(|line| line.find("//").map(|i| line[i..].trim_end()))
+            .collect()
+    }
+
+    const SOURCES: [&str; 8] = [
+        "var a=1;print a+2 ;",
+        "// header\n\n\nvar a = 1; // one\n\n// about f\nfunc f(x) {\n  // inside\n  return x; // two\n}\n// at the end\n",
+        "if (a == 1) // why\n{\n    print a;\n}\n// before else\nelse {\n    print 2;\n}\n",
+        "if (a) {\n  print 1;\n} // after then\nelse print 2;\n",
+        "while (a < 0) // loop\n// more\n{ a = a + 1; }\nfunc f() // doc\n{}\n",
+        "try { throw 1; }\n  // before catch\n catch (e) { print e; }\n",
+        "class A // the class\n{ init() { this.x = [1, 2]; } // trailing\n  m() { return {\"k\": this.x}; } }\n",
+        "var b = 1 + // split\n    2;\nprint b ? \"y\" : \"n\";\n",
+    ];
+
+    #[test]
+    fn formatting_twice_changes_nothing() {
+        for source in SOURCES {
+            let formatted = format(source);
+            assert_eq!(format(&formatted), formatted, "{source}");
+        }
+    }
+
+    #[test]
+    fn comments_are_kept_in_order() {
+        for source in SOURCES {
+            assert_eq!(comments(&format(source)), comments(source), "{source}");
+        }
+    }
+
+    #[test]
+    fn comments_before_a_brace_go_after_it() {
+        assert_eq!(
+            format(SOURCES[2]),
+            "if (a == 1) { // why\n    print a;\n}\n// before else\nelse {\n    print 2;\n}\n"
+        );
+        assert_eq!(
+            format(SOURCES[4]),
+            "while (a < 0) { // loop\n    // more\n    a = a + 1;\n}\nfunc f() { // doc\n}\n"
+        );
+    }
+
+    #[test]
+    fn else_and_catch_after_a_comment_start_a_line() {
+        assert_eq!(
+            format(SOURCES[3]),
+            "if (a) {\n    print 1;\n} // after then\nelse\n    print 2;\n"
+        );
+        assert_eq!(
+            format(SOURCES[5]),
+            "try {\n    throw 1;\n}\n// before catch\ncatch (e) {\n    print e;\n}\n"
+        );
+    }
+}
@@ -1,92 +1,1047 @@
-#![allow(unused)]
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
 
-use crate::interpreter::environment::{Environment, Value};
-use crate::parser::exprstmt::{self, BinOpType, Expr, Literal, Stmt, UniOpType};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::interpreter::environment::{
+    this_symbol, Class, Environment, ErrorValue, Function, Globals, Instance, Map, Value,
+};
+use crate::interpreter::native::{NativeFunction, Registry};
+use crate::parser::exprstmt::{
+    self, Expr, Literal, LogicOpType, Slot, SourceLocation, Stmt, Symbol,
+};
+use crate::stdlib;
 
-mod environment {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    TypeError,
+    DivisionByZero,
+    UndefinedVariable,
+    UninitializedVariable,
+    NotCallable,
+    ArityMismatch,
+    StackOverflow,
+    NativeError,
+    UnknownProperty,
+    Thrown,
+    IndexOutOfBounds,
+    MissingKey,
+}
+
+impl ErrorKind {
+    fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::TypeError => "E0201",
+            ErrorKind::DivisionByZero => "E0202",
+            ErrorKind::UndefinedVariable => "E0203",
+            ErrorKind::UninitializedVariable => "E0204",
+            ErrorKind::NotCallable => "E0206",
+            ErrorKind::ArityMismatch => "E0207",
+            ErrorKind::StackOverflow => "E0208",
+            ErrorKind::NativeError => "E0209",
+            ErrorKind::UnknownProperty => "E0210",
+            ErrorKind::Thrown => "E0211",
+            ErrorKind::IndexOutOfBounds => "E0212",
+            ErrorKind::MissingKey => "E0213",
+        }
+    }
+}
+
+//runtime errors, a try statement can catch them as an error value
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Span,
+    //the value given to throw, for errors raised by the program itself
+    pub thrown: Option<Value>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>, span: Span) -> RuntimeError {
+        RuntimeError {
+            kind,
+            message: message.into(),
+            span,
+            thrown: None,
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        match self.kind {
+            ErrorKind::Thrown => Diagnostic::error(
+                self.kind.code(),
+                format!("Uncaught error: {}", self.message),
+                self.span,
+            )
+            .with_note("use try { ... } catch (e) { ... } to handle it"),
+            _ => Diagnostic::error(self.kind.code(), self.message.clone(), self.span),
+        }
+    }
+
+    //the error raised by a throw statement, span is the 'throw' keyword
+    pub fn thrown(val: Value, span: Span) -> RuntimeError {
+        let message = match &val {
+            Value::Error(err) => err.message.clone(),
+            _ => val.to_string(),
+        };
+        let mut err = RuntimeError::new(ErrorKind::Thrown, message, span);
+        err.thrown = Some(val);
+        err
+    }
+
+    //the value a catch block gets for this error
+    pub fn to_value(&self) -> Value {
+        if let Some(Value::Error(err)) = &self.thrown {
+            return Value::Error(err.clone());
+        }
+        Value::Error(Rc::new(ErrorValue {
+            kind: format!("{:?}", self.kind),
+            message: self.message.clone(),
+            line: self.span.line,
+            column: self.span.column,
+            value: self.thrown.clone().unwrap_or(Value::Null),
+        }))
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[line: {}, column: {}] {}",
+            self.span.line, self.span.column, self.message
+        )
+    }
+}
+
+pub mod environment {
+    use std::cell::RefCell;
     use std::collections::HashMap;
     use std::fmt;
+    use std::rc::Rc;
+
+    use super::native::NativeFunction;
+    use super::{ErrorKind, RuntimeError};
+    use crate::diagnostic::Span;
+    use crate::parser::exprstmt::{FunctionDecl, Slot, Symbol};
+    use crate::vm::{BoundMethod, Closure};
 
-    use crate::parser::exprstmt::Symbol;
+    //one local scope, the resolver has already worked out the slot of every variable,
+    //so values are kept in the order they were declared
     #[derive(Clone, Debug, Default)]
     pub struct Environment {
-        pub values: HashMap<String, Option<Value>>,
+        pub values: Vec<Option<Value>>,
+        pub enclosing: Option<Rc<RefCell<Environment>>>,
     }
 
     impl Environment {
-        pub fn define(&mut self, sym: Symbol, value: Option<Value>) {
-            self.values.insert(sym.name, value);
+        pub fn new_enclosed(enclosing: Rc<RefCell<Environment>>) -> Environment {
+            Environment {
+                values: Vec::new(),
+                enclosing: Some(enclosing),
+            }
         }
 
-        pub fn assign(&mut self, sym: Symbol, val: &Value) -> Result<(), String> {
-            if self.values.contains_key(&sym.name) {
-                self.define(sym, Some(val.clone()));
-                return Ok(());
+        //takes the next slot
+        pub fn define(&mut self, value: Option<Value>) {
+            self.values.push(value);
+        }
+
+        //the scope depth levels out from env
+        fn ancestor(env: &Rc<RefCell<Environment>>, depth: usize) -> Rc<RefCell<Environment>> {
+            let mut env = env.clone();
+            for _ in 0..depth {
+                let enclosing = env.borrow().enclosing.clone();
+                env = enclosing.expect("internal error: resolved scope is missing");
+            }
+            env
+        }
+
+        pub fn get_at(
+            env: &Rc<RefCell<Environment>>,
+            slot: Slot,
+            sym: &Symbol,
+        ) -> Result<Value, RuntimeError> {
+            let env = Environment::ancestor(env, slot.depth);
+            let val = env.borrow().values[slot.index].clone();
+            val.ok_or_else(|| uninitialized(&sym.name, sym.span()))
+        }
+
+        pub fn assign_at(env: &Rc<RefCell<Environment>>, slot: Slot, val: &Value) {
+            let env = Environment::ancestor(env, slot.depth);
+            env.borrow_mut().values[slot.index] = Some(val.clone());
+        }
+    }
+
+    //the top-level variables, found by name since the repl may redefine them
+    #[derive(Debug, Default)]
+    pub struct Globals {
+        values: HashMap<String, Option<Value>>,
+    }
+
+    impl Globals {
+        pub fn define(&mut self, name: &str, value: Option<Value>) {
+            self.values.insert(name.to_string(), value);
+        }
+
+        //span is where the variable is used, for the error
+        pub fn assign(&mut self, name: &str, val: &Value, span: Span) -> Result<(), RuntimeError> {
+            match self.values.get_mut(name) {
+                Some(slot) => {
+                    *slot = Some(val.clone());
+                    Ok(())
+                }
+                None => Err(RuntimeError::new(
+                    ErrorKind::UndefinedVariable,
+                    format!("Attempted to assign to an undefined variable {name}"),
+                    span,
+                )),
             }
-            Err(format!("attempted to assign to an undefined variable"))
         }
 
-        pub fn get(&self, name: &String) -> Result<Value, String> {
-            if self.values.contains_key(name) {
-                Ok(self.values[name].clone().unwrap()) //might not be correct
-            } else {
-                Err(format!("Undefined variable {}", name))
+        pub fn get(&self, name: &str, span: Span) -> Result<Value, RuntimeError> {
+            match self.values.get(name) {
+                Some(Some(v)) => Ok(v.clone()),
+                Some(None) => Err(uninitialized(name, span)),
+                None => Err(RuntimeError::new(
+                    ErrorKind::UndefinedVariable,
+                    format!("Undefined variable {name}"),
+                    span,
+                )),
             }
         }
     }
 
+    pub fn uninitialized(name: &str, span: Span) -> RuntimeError {
+        RuntimeError::new(
+            ErrorKind::UninitializedVariable,
+            format!("Variable {name} was used before it was given a value"),
+            span,
+        )
+    }
+
     #[derive(Clone, Debug)]
     pub enum Value {
         Number(f64),
-        String(String),
+        String(Rc<str>),
         Bool(bool),
         Null,
+        Function(Rc<Function>),
+        Native(Rc<dyn NativeFunction>),
+        //functions compiled for the vm, and vm methods bound to their instance
+        Closure(Rc<Closure>),
+        BoundMethod(Rc<BoundMethod>),
+        Class(Rc<Class>),
+        Instance(Rc<RefCell<Instance>>),
+        Error(Rc<ErrorValue>),
+        //lists are shared, assigning one to another variable doesn't copy it
+        List(Rc<RefCell<Vec<Value>>>),
+        Map(Rc<RefCell<Map>>),
+    }
+
+    //a map remembers the order its keys were added in, so printing it is deterministic
+    #[derive(Debug, Default)]
+    pub struct Map {
+        keys: Vec<MapKey>,
+        values: HashMap<MapKey, Value>,
+    }
+
+    impl Map {
+        pub fn get(&self, key: &MapKey) -> Option<&Value> {
+            self.values.get(key)
+        }
+
+        pub fn insert(&mut self, key: MapKey, val: Value) {
+            if !self.values.contains_key(&key) {
+                self.keys.push(key.clone());
+            }
+            self.values.insert(key, val);
+        }
+
+        pub fn keys(&self) -> &[MapKey] {
+            &self.keys
+        }
+
+        pub fn len(&self) -> usize {
+            self.keys.len()
+        }
+    }
+
+    //the values that can be map keys, numbers are kept as their bits so they can be hashed
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub enum MapKey {
+        String(Rc<str>),
+        Number(u64),
+        Bool(bool),
+    }
+
+    impl MapKey {
+        pub fn from_value(val: &Value) -> Option<MapKey> {
+            match val {
+                Value::String(s) => Some(MapKey::String(s.clone())),
+                //0 and -0 are equal, so they have to be the same key
+                Value::Number(n) if *n == 0.0 => Some(MapKey::Number(0f64.to_bits())),
+                Value::Number(n) if !n.is_nan() => Some(MapKey::Number(n.to_bits())),
+                Value::Bool(b) => Some(MapKey::Bool(*b)),
+                _ => None,
+            }
+        }
+
+        pub fn to_value(&self) -> Value {
+            match self {
+                MapKey::String(s) => Value::String(s.clone()),
+                MapKey::Number(bits) => Value::Number(f64::from_bits(*bits)),
+                MapKey::Bool(b) => Value::Bool(*b),
+            }
+        }
+    }
+
+    //a user-defined function and the scope it was declared in
+    pub struct Function {
+        pub decl: Rc<FunctionDecl>,
+        pub closure: Rc<RefCell<Environment>>,
+        pub is_initializer: bool,
+    }
+
+    impl Function {
+        //a copy of the method with 'this' defined in a scope around its body
+        pub fn bind(&self, instance: Rc<RefCell<Instance>>) -> Function {
+            let mut env = Environment::new_enclosed(self.closure.clone());
+            env.define(Some(Value::Instance(instance)));
+            Function {
+                decl: self.decl.clone(),
+                closure: Rc::new(RefCell::new(env)),
+                is_initializer: self.is_initializer,
+            }
+        }
+    }
+
+    pub fn this_symbol() -> Symbol {
+        Symbol {
+            name: "this".to_string(),
+            line: 0,
+            column: 0,
+        }
+    }
+
+    //the methods are Functions in the interpreter and Closures in the vm
+    #[derive(Debug)]
+    pub struct Class {
+        pub name: String,
+        pub methods: HashMap<String, Value>,
+    }
+
+    //the fields can contain the instance itself, so Debug only shows the class
+    pub struct Instance {
+        pub class: Rc<Class>,
+        pub fields: HashMap<String, Value>,
+    }
+
+    impl fmt::Debug for Instance {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Instance({})", self.class.name)
+        }
+    }
+
+    impl Instance {
+        //fields shadow methods with the same name
+        pub fn get(
+            instance: &Rc<RefCell<Instance>>,
+            name: &str,
+            span: Span,
+        ) -> Result<Value, RuntimeError> {
+            if let Some(val) = instance.borrow().fields.get(name) {
+                return Ok(val.clone());
+            }
+            let class = instance.borrow().class.clone();
+            match class.methods.get(name) {
+                Some(Value::Function(method)) => {
+                    Ok(Value::Function(Rc::new(method.bind(instance.clone()))))
+                }
+                Some(Value::Closure(method)) => Ok(Value::BoundMethod(Rc::new(BoundMethod {
+                    receiver: instance.clone(),
+                    method: method.clone(),
+                }))),
+                Some(method) => Ok(method.clone()),
+                None => Err(RuntimeError::new(
+                    ErrorKind::UnknownProperty,
+                    format!("{} instance has no property {name}", class.name),
+                    span,
+                )),
+            }
+        }
+    }
+
+    //what a catch block gets, runtime errors use the name of their ErrorKind as the kind
+    #[derive(Debug)]
+    pub struct ErrorValue {
+        pub kind: String,
+        pub message: String,
+        pub line: usize,
+        pub column: i64,
+        //the thrown value, null for runtime errors
+        pub value: Value,
+    }
+
+    impl ErrorValue {
+        pub fn get(&self, name: &str, span: Span) -> Result<Value, RuntimeError> {
+            match name {
+                "kind" => Ok(self.kind.as_str().into()),
+                "message" => Ok(self.message.as_str().into()),
+                "line" => Ok(Value::Number(self.line as f64)),
+                "column" => Ok(Value::Number(self.column as f64)),
+                "value" => Ok(self.value.clone()),
+                _ => Err(RuntimeError::new(
+                    ErrorKind::UnknownProperty,
+                    format!(
+                        "error has no property {name}, try kind, message, line, column or value"
+                    ),
+                    span,
+                )),
+            }
+        }
+    }
+
+    //the closure can contain the function itself, so it isn't printed
+    impl fmt::Debug for Function {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Function({})", self.decl.name.name)
+        }
+    }
+
+    impl Value {
+        pub fn type_name(&self) -> &'static str {
+            match self {
+                Value::Number(_) => "a number",
+                Value::String(_) => "a string",
+                Value::Bool(_) => "a boolean",
+                Value::Null => "null",
+                Value::Function(_)
+                | Value::Native(_)
+                | Value::Closure(_)
+                | Value::BoundMethod(_) => "a function",
+                Value::Class(_) => "a class",
+                Value::Instance(_) => "an instance",
+                Value::Error(_) => "an error",
+                Value::List(_) => "a list",
+                Value::Map(_) => "a map",
+            }
+        }
+
+        //seen holds the lists and maps being printed,
+        //so one that contains itself prints as [...] or {...}
+        fn write(&self, f: &mut fmt::Formatter, seen: &mut Vec<*const ()>) -> fmt::Result {
+            match self {
+                Value::List(list) => {
+                    let ptr = Rc::as_ptr(list) as *const ();
+                    if seen.contains(&ptr) {
+                        return write!(f, "[...]");
+                    }
+                    seen.push(ptr);
+                    write!(f, "[")?;
+                    for (i, val) in list.borrow().iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        val.write_element(f, seen)?;
+                    }
+                    seen.pop();
+                    write!(f, "]")
+                }
+                Value::Map(map) => {
+                    let ptr = Rc::as_ptr(map) as *const ();
+                    if seen.contains(&ptr) {
+                        return write!(f, "{{...}}");
+                    }
+                    seen.push(ptr);
+                    write!(f, "{{")?;
+                    let map = map.borrow();
+                    for (i, key) in map.keys().iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{key}: ")?;
+                        map.values[key].write_element(f, seen)?;
+                    }
+                    seen.pop();
+                    write!(f, "}}")
+                }
+                _ => write!(f, "{}", self),
+            }
+        }
+
+        //strings are quoted inside lists and maps, so ["a, b"] and ["a", "b"] differ
+        fn write_element(&self, f: &mut fmt::Formatter, seen: &mut Vec<*const ()>) -> fmt::Result {
+            match self {
+                Value::String(s) => write!(f, "{:?}", s),
+                _ => self.write(f, seen),
+            }
+        }
+    }
+
+    //keys print the way they are written in a map literal
+    impl fmt::Display for MapKey {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                MapKey::String(s) => write!(f, "{:?}", s),
+                _ => write!(f, "{}", self.to_value()),
+            }
+        }
     }
 
     impl fmt::Display for Value {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             match self {
+                Value::List(_) | Value::Map(_) => self.write(f, &mut Vec::new()),
                 Value::Number(n) => write!(f, "{}", n),
                 Value::String(s) => write!(f, "{}", s.clone()),
                 Value::Bool(b) => write!(f, "{}", b),
                 Value::Null => write!(f, "null"),
+                Value::Function(func) => write!(f, "<func {}>", func.decl.name.name),
+                Value::Native(func) => write!(f, "<native func {}>", func.name()),
+                Value::Closure(closure) => write!(f, "<func {}>", closure.proto.name),
+                Value::BoundMethod(bound) => write!(f, "<func {}>", bound.method.proto.name),
+                Value::Class(class) => write!(f, "<class {}>", class.name),
+                Value::Instance(instance) => {
+                    write!(f, "<{} instance>", instance.borrow().class.name)
+                }
+                Value::Error(err) => write!(f, "{}: {}", err.kind, err.message),
+            }
+        }
+    }
+}
+
+//functions written in rust that apprentice programs can call
+pub mod native {
+    use std::fmt;
+
+    use super::environment::Value;
+
+    pub trait NativeFunction {
+        fn name(&self) -> &str;
+        fn arity(&self) -> usize;
+        //the arguments are already checked against the arity,
+        //an Err is reported as a runtime error at the call
+        fn call(&self, args: Vec<Value>) -> Result<Value, String>;
+    }
+
+    //a backend that natives can be defined in, the interpreter or the vm
+    pub trait Registry {
+        fn define_native(&mut self, func: impl NativeFunction + 'static);
+    }
+
+    impl fmt::Debug for dyn NativeFunction {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "Native({})", self.name())
+        }
+    }
+
+    type NativeBody = dyn Fn(Vec<Value>) -> Result<Value, String>;
+
+    //a native function made from a closure, for when a whole type is overkill
+    pub struct NativeFn {
+        name: String,
+        arity: usize,
+        func: Box<NativeBody>,
+    }
+
+    impl NativeFn {
+        pub fn new<F>(name: &str, arity: usize, func: F) -> NativeFn
+        where
+            F: Fn(Vec<Value>) -> Result<Value, String> + 'static,
+        {
+            NativeFn {
+                name: name.to_string(),
+                arity,
+                func: Box::new(func),
+            }
+        }
+    }
+
+    impl NativeFunction for NativeFn {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn arity(&self) -> usize {
+            self.arity
+        }
+
+        fn call(&self, args: Vec<Value>) -> Result<Value, String> {
+            (self.func)(args)
+        }
+    }
+
+    // conversions between rust types and values
+
+    impl From<f64> for Value {
+        fn from(n: f64) -> Value {
+            Value::Number(n)
+        }
+    }
+
+    impl From<bool> for Value {
+        fn from(b: bool) -> Value {
+            Value::Bool(b)
+        }
+    }
+
+    impl From<String> for Value {
+        fn from(s: String) -> Value {
+            Value::String(s.into())
+        }
+    }
+
+    impl From<&str> for Value {
+        fn from(s: &str) -> Value {
+            Value::String(s.into())
+        }
+    }
+
+    impl From<()> for Value {
+        fn from(_: ()) -> Value {
+            Value::Null
+        }
+    }
+
+    impl<T: Into<Value>> From<Option<T>> for Value {
+        fn from(val: Option<T>) -> Value {
+            match val {
+                Some(v) => v.into(),
+                None => Value::Null,
+            }
+        }
+    }
+
+    impl TryFrom<Value> for f64 {
+        type Error = String;
+
+        fn try_from(val: Value) -> Result<f64, String> {
+            match val {
+                Value::Number(n) => Ok(n),
+                _ => Err(format!("expected a number, found {}", val.type_name())),
+            }
+        }
+    }
+
+    impl TryFrom<Value> for bool {
+        type Error = String;
+
+        fn try_from(val: Value) -> Result<bool, String> {
+            match val {
+                Value::Bool(b) => Ok(b),
+                _ => Err(format!("expected a boolean, found {}", val.type_name())),
+            }
+        }
+    }
+
+    impl TryFrom<Value> for String {
+        type Error = String;
+
+        fn try_from(val: Value) -> Result<String, String> {
+            match val {
+                Value::String(s) => Ok(s.to_string()),
+                _ => Err(format!("expected a string, found {}", val.type_name())),
             }
         }
     }
 }
 
+//operations on values shared by the interpreter and the vm, so both backends behave the same
+pub mod ops {
+    use std::rc::Rc;
+
+    use super::environment::{Instance, MapKey, Value};
+    use super::native::NativeFunction;
+    use super::{ErrorKind, RuntimeError};
+    use crate::diagnostic::Span;
+    use crate::parser::exprstmt::{BinOpType, LogicOpType, UniOpType};
+
+    pub fn unary(op: UniOpType, val: &Value, span: Span) -> Result<Value, RuntimeError> {
+        match (op, val) {
+            (UniOpType::Minus, Value::Number(n)) => Ok(Value::Number(-n)),
+            (UniOpType::Bang, Value::Bool(b)) => Ok(Value::Bool(!b)),
+            (UniOpType::Minus, _) => Err(RuntimeError::new(
+                ErrorKind::TypeError,
+                format!("Can't negate {}, expected a number", val.type_name()),
+                span,
+            )),
+            (UniOpType::Bang, _) => Err(RuntimeError::new(
+                ErrorKind::TypeError,
+                format!("Can't apply '!' to {}, expected a boolean", val.type_name()),
+                span,
+            )),
+        }
+    }
+
+    pub fn binary(op: BinOpType, l: &Value, r: &Value, span: Span) -> Result<Value, RuntimeError> {
+        match (l, op, r) {
+            (Value::Number(l), BinOpType::Less, Value::Number(r)) => Ok(Value::Bool(l < r)),
+            (Value::Number(l), BinOpType::LessEqual, Value::Number(r)) => Ok(Value::Bool(l <= r)),
+            (Value::Number(l), BinOpType::Greater, Value::Number(r)) => Ok(Value::Bool(l > r)),
+            (Value::Number(l), BinOpType::GreaterEqual, Value::Number(r)) => {
+                Ok(Value::Bool(l >= r))
+            }
+            (Value::Number(l), BinOpType::Sub, Value::Number(r)) => Ok(Value::Number(l - r)),
+            (Value::Number(l), BinOpType::Add, Value::Number(r)) => Ok(Value::Number(l + r)),
+            (Value::Number(l), BinOpType::Mult, Value::Number(r)) => Ok(Value::Number(l * r)),
+            (Value::Number(l), BinOpType::Div, Value::Number(r)) => {
+                if *r == 0.0 {
+                    Err(RuntimeError::new(
+                        ErrorKind::DivisionByZero,
+                        "Can't divide by zero",
+                        span,
+                    ))
+                } else {
+                    Ok(Value::Number(l / r))
+                }
+            }
+            (Value::String(l), BinOpType::Add, Value::String(r)) => Ok(format!("{l}{r}").into()),
+            (_, BinOpType::EqualEqual, _) => Ok(Value::Bool(equals(l, r))),
+            (_, BinOpType::NotEqual, _) => Ok(Value::Bool(!equals(l, r))),
+
+            _ => Err(RuntimeError::new(
+                ErrorKind::TypeError,
+                format!(
+                    "Can't apply '{}' to {} and {}",
+                    op,
+                    l.type_name(),
+                    r.type_name(),
+                ),
+                span,
+            )),
+        }
+    }
+
+    //the operands of 'and' and 'or' have to be booleans
+    pub fn logical_operand(op: LogicOpType, val: &Value, span: Span) -> Result<bool, RuntimeError> {
+        match val {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(RuntimeError::new(
+                ErrorKind::TypeError,
+                format!(
+                    "Can't apply '{}' to {}, expected a boolean",
+                    op,
+                    val.type_name()
+                ),
+                span,
+            )),
+        }
+    }
+
+    //the condition of an if, loop or '?' has to be a boolean, span is the keyword
+    pub fn condition(keyword: &str, val: &Value, span: Span) -> Result<bool, RuntimeError> {
+        match val {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(RuntimeError::new(
+                ErrorKind::TypeError,
+                format!(
+                    "The condition of '{keyword}' must be a boolean, found {}",
+                    val.type_name()
+                ),
+                span,
+            )),
+        }
+    }
+
+    pub fn get_property(object: &Value, name: &str, span: Span) -> Result<Value, RuntimeError> {
+        match object {
+            Value::Instance(instance) => Instance::get(instance, name, span),
+            Value::Error(err) => err.get(name, span),
+            _ => Err(RuntimeError::new(
+                ErrorKind::TypeError,
+                format!(
+                    "Only instances have properties, found {}",
+                    object.type_name()
+                ),
+                span,
+            )),
+        }
+    }
+
+    pub fn set_property(
+        object: &Value,
+        name: &str,
+        val: Value,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        match object {
+            Value::Instance(instance) => {
+                instance.borrow_mut().fields.insert(name.to_string(), val);
+                Ok(())
+            }
+            _ => Err(RuntimeError::new(
+                ErrorKind::TypeError,
+                format!("Only instances have fields, found {}", object.type_name()),
+                span,
+            )),
+        }
+    }
+
+    //span is the '['
+    pub fn get_index(object: &Value, index: &Value, span: Span) -> Result<Value, RuntimeError> {
+        match object {
+            Value::List(list) => {
+                let list = list.borrow();
+                let i = list_index(list.len(), index, span)?;
+                Ok(list[i].clone())
+            }
+            Value::Map(map) => {
+                let key = map_key(index, span)?;
+                let val = map.borrow().get(&key).cloned();
+                val.ok_or_else(|| {
+                    RuntimeError::new(ErrorKind::MissingKey, format!("Map has no key {key}"), span)
+                })
+            }
+            _ => Err(not_indexable(object, span)),
+        }
+    }
+
+    pub fn set_index(
+        object: &Value,
+        index: &Value,
+        val: Value,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        match object {
+            Value::List(list) => {
+                let i = list_index(list.borrow().len(), index, span)?;
+                list.borrow_mut()[i] = val;
+                Ok(())
+            }
+            Value::Map(map) => {
+                let key = map_key(index, span)?;
+                map.borrow_mut().insert(key, val);
+                Ok(())
+            }
+            _ => Err(not_indexable(object, span)),
+        }
+    }
+
+    fn not_indexable(val: &Value, span: Span) -> RuntimeError {
+        RuntimeError::new(
+            ErrorKind::TypeError,
+            format!(
+                "Only lists and maps can be indexed, found {}",
+                val.type_name()
+            ),
+            span,
+        )
+    }
+
+    //span is the '[' or the '{' of a map literal
+    pub fn map_key(val: &Value, span: Span) -> Result<MapKey, RuntimeError> {
+        MapKey::from_value(val).ok_or_else(|| {
+            RuntimeError::new(
+                ErrorKind::TypeError,
+                format!(
+                    "Map keys must be strings, numbers or booleans, found {}",
+                    val.type_name()
+                ),
+                span,
+            )
+        })
+    }
+
+    //checks that the index is a whole number inside the list
+    fn list_index(len: usize, index: &Value, span: Span) -> Result<usize, RuntimeError> {
+        let n = match index {
+            Value::Number(n) => *n,
+            _ => {
+                return Err(RuntimeError::new(
+                    ErrorKind::TypeError,
+                    format!("List index must be a number, found {}", index.type_name()),
+                    span,
+                ))
+            }
+        };
+        if n.fract() != 0.0 {
+            return Err(RuntimeError::new(
+                ErrorKind::TypeError,
+                format!("List index must be a whole number, found {n}"),
+                span,
+            ));
+        }
+        if n < 0.0 {
+            return Err(RuntimeError::new(
+                ErrorKind::IndexOutOfBounds,
+                format!("List index can't be negative, found {n}"),
+                span,
+            ));
+        }
+        if n >= len as f64 {
+            return Err(RuntimeError::new(
+                ErrorKind::IndexOutOfBounds,
+                format!("Index {n} is out of bounds for a list of length {len}"),
+                span,
+            ));
+        }
+        Ok(n as usize)
+    }
+
+    pub fn equals(left: &Value, right: &Value) -> bool {
+        match (left, right) {
+            (Value::Number(n1), Value::Number(n2)) => (n1 - n2).abs() < f64::EPSILON,
+            (Value::String(s1), Value::String(s2)) => s1 == s2,
+            (Value::Bool(b1), Value::Bool(b2)) => b1 == b2,
+            (Value::Null, Value::Null) => true,
+            (Value::Function(f1), Value::Function(f2)) => Rc::ptr_eq(f1, f2),
+            (Value::Native(f1), Value::Native(f2)) => Rc::ptr_eq(f1, f2),
+            (Value::Class(c1), Value::Class(c2)) => Rc::ptr_eq(c1, c2),
+            (Value::Instance(i1), Value::Instance(i2)) => Rc::ptr_eq(i1, i2),
+            (Value::Error(e1), Value::Error(e2)) => Rc::ptr_eq(e1, e2),
+            (Value::List(l1), Value::List(l2)) => Rc::ptr_eq(l1, l2),
+            (Value::Map(m1), Value::Map(m2)) => Rc::ptr_eq(m1, m2),
+            (Value::Closure(c1), Value::Closure(c2)) => Rc::ptr_eq(c1, c2),
+            (Value::BoundMethod(b1), Value::BoundMethod(b2)) => Rc::ptr_eq(b1, b2),
+            (_, _) => false,
+        }
+    }
+
+    //span is the '(' of the call in these
+    pub fn not_callable(val: &Value, span: Span) -> RuntimeError {
+        RuntimeError::new(
+            ErrorKind::NotCallable,
+            format!("Can only call functions, found {}", val.type_name()),
+            span,
+        )
+    }
+
+    pub fn arity_mismatch(name: &str, expected: usize, got: usize, span: Span) -> RuntimeError {
+        RuntimeError::new(
+            ErrorKind::ArityMismatch,
+            format!("{name} expects {expected} argument(s) but got {got}"),
+            span,
+        )
+    }
+
+    pub fn stack_overflow(name: &str, span: Span) -> RuntimeError {
+        RuntimeError::new(
+            ErrorKind::StackOverflow,
+            format!("Too many nested calls, is {name} calling itself forever?"),
+            span,
+        )
+    }
+
+    pub fn call_native(
+        func: &dyn NativeFunction,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        if args.len() != func.arity() {
+            return Err(arity_mismatch(func.name(), func.arity(), args.len(), span));
+        }
+        func.call(args).map_err(|message| {
+            RuntimeError::new(
+                ErrorKind::NativeError,
+                format!("{}: {message}", func.name()),
+                span,
+            )
+        })
+    }
+}
+
+//how deep calls can nest before the program is stopped
+pub const MAX_CALL_DEPTH: usize = 255;
+
+//every call and nested expression recurses on the native stack, main runs everything
+//on a thread this big so MAX_CALL_DEPTH calls fit even in a debug build
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+//calls with deeply nested expressions run out before MAX_CALL_DEPTH, a call that finds
+//this much of the stack used is stopped too, the rest is room for the deepest expression
+const MAX_STACK_USE: usize = STACK_SIZE - 32 * 1024 * 1024;
+
+//an address on the native stack, the difference between two is how much is used between them
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+//how a statement finished, loops react to break and continue, calls to return
+#[derive(Debug, Clone)]
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+#[derive(Default)]
 pub struct Interpreter {
-    env: Environment,
+    //the innermost scope, blocks swap in a new one while they run
+    env: Rc<RefCell<Environment>>,
+    globals: Globals,
+    call_depth: usize,
+    //where the stack was when the interpreter was made, stacks grow down
+    stack_base: usize,
 }
 
-pub fn interpret(stmts: &Vec<Stmt>) -> Result<(), String> {
-    let mut i = Interpreter {
-        env: Environment {
-            ..Default::default()
-        },
-    };
+pub fn interpret(stmts: &[Stmt]) -> Result<(), RuntimeError> {
+    let mut i = Interpreter::new();
     i.interpret(stmts)
 }
 
+//natives are defined as global variables
+impl Registry for Interpreter {
+    fn define_native(&mut self, func: impl NativeFunction + 'static) {
+        let name = func.name().to_string();
+        self.globals
+            .define(&name, Some(Value::Native(Rc::new(func))));
+    }
+}
+
 impl Interpreter {
-    pub fn interpret(&mut self, stmts: &Vec<Stmt>) -> Result<(), String> {
+    //an interpreter with the standard library already defined
+    pub fn new() -> Interpreter {
+        let mut interpreter = Interpreter {
+            env: Rc::new(RefCell::new(Environment {
+                ..Default::default()
+            })),
+            globals: Globals::default(),
+            call_depth: 0,
+            stack_base: stack_address(),
+        };
+        stdlib::register(&mut interpreter);
+        interpreter
+    }
+
+    //top-level declarations are globals, any other takes the next slot of the current scope
+    fn declare(&mut self, sym: &Symbol, val: Option<Value>) {
+        if self.env.borrow().enclosing.is_none() {
+            self.globals.define(&sym.name, val);
+        } else {
+            self.env.borrow_mut().define(val);
+        }
+    }
+
+    //slot is where the resolver found the variable, None for globals
+    fn lookup(&self, sym: &Symbol, slot: Option<Slot>) -> Result<Value, RuntimeError> {
+        match slot {
+            Some(slot) => Environment::get_at(&self.env, slot, sym),
+            None => self.globals.get(&sym.name, sym.span()),
+        }
+    }
+
+    pub fn interpret(&mut self, stmts: &[Stmt]) -> Result<(), RuntimeError> {
         for stmt in stmts {
             self.execute(stmt)?;
         }
         Ok(())
     }
 
-    fn execute(&mut self, stmt: &Stmt) -> Result<(), String> {
+    fn execute(&mut self, stmt: &Stmt) -> Result<Flow, RuntimeError> {
         match stmt {
             Stmt::Print(e) => match self.interpret_expr(e) {
                 Ok(v) => {
                     println!("{v}");
-                    Ok(())
+                    Ok(Flow::Normal)
                 }
                 Err(err) => Err(err),
             },
             Stmt::Expression(e) => match self.interpret_expr(e) {
-                Ok(_) => Ok(()),
+                Ok(_) => Ok(Flow::Normal),
                 Err(err) => Err(err),
             },
             Stmt::VarDeclaration(s, e) => {
@@ -94,23 +1049,213 @@ impl Interpreter {
                     Some(expr) => Some(self.interpret_expr(expr)?),
                     None => None,
                 };
-                self.env.define(s.clone(), val);
-                Ok(())
+                self.declare(s, val);
+                Ok(Flow::Normal)
+            }
+            Stmt::Block(stmts) => {
+                let env = Environment::new_enclosed(self.env.clone());
+                self.execute_block(stmts, env)
+            }
+            Stmt::If(loc, cond, then_branch, else_branch) => {
+                if self.condition(cond, loc, "if")? {
+                    self.execute(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)
+                } else {
+                    Ok(Flow::Normal)
+                }
+            }
+            Stmt::While(loc, cond, body) => {
+                while self.condition(cond, loc, "while")? {
+                    match self.execute(body)? {
+                        Flow::Break => break,
+                        Flow::Return(val) => return Ok(Flow::Return(val)),
+                        Flow::Normal | Flow::Continue => (),
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Stmt::For(loc, init, cond, increment, body) => {
+                //the loop variable lives in its own scope around the loop
+                let env = Environment::new_enclosed(self.env.clone());
+                let previous = std::mem::replace(&mut self.env, Rc::new(RefCell::new(env)));
+                let result = self.execute_for(loc, init, cond, increment, body);
+                self.env = previous;
+                result
+            }
+            Stmt::Break => Ok(Flow::Break),
+            Stmt::Continue => Ok(Flow::Continue),
+            Stmt::Function(decl) => {
+                let func = Function {
+                    decl: decl.clone(),
+                    closure: self.env.clone(),
+                    is_initializer: false,
+                };
+                self.declare(&decl.name, Some(Value::Function(Rc::new(func))));
+                Ok(Flow::Normal)
+            }
+            Stmt::Class(decl) => {
+                let methods = decl
+                    .methods
+                    .iter()
+                    .map(|method| {
+                        let func = Function {
+                            decl: method.clone(),
+                            closure: self.env.clone(),
+                            is_initializer: method.name.name == "init",
+                        };
+                        (method.name.name.clone(), Value::Function(Rc::new(func)))
+                    })
+                    .collect();
+                let class = Class {
+                    name: decl.name.name.clone(),
+                    methods,
+                };
+                self.declare(&decl.name, Some(Value::Class(Rc::new(class))));
+                Ok(Flow::Normal)
+            }
+            Stmt::Throw(loc, e) => {
+                let val = self.interpret_expr(e)?;
+                Err(RuntimeError::thrown(val, loc.span("throw".len())))
+            }
+            //any runtime error in the body is caught, the handler gets it as an error value
+            Stmt::Try(body, _name, handler) => {
+                let env = Environment::new_enclosed(self.env.clone());
+                match self.execute_block(body, env) {
+                    Err(err) => {
+                        let mut env = Environment::new_enclosed(self.env.clone());
+                        env.define(Some(err.to_value()));
+                        self.execute_block(handler, env)
+                    }
+                    flow => flow,
+                }
+            }
+            Stmt::Return(e) => {
+                let val = match e {
+                    Some(expr) => self.interpret_expr(expr)?,
+                    None => Value::Null,
+                };
+                Ok(Flow::Return(val))
+            }
+        }
+    }
+
+    fn execute_for(
+        &mut self,
+        loc: &SourceLocation,
+        init: &Option<Box<Stmt>>,
+        cond: &Option<Expr>,
+        increment: &Option<Expr>,
+        body: &Stmt,
+    ) -> Result<Flow, RuntimeError> {
+        if let Some(init) = init {
+            self.execute(init)?;
+        }
+        loop {
+            if let Some(cond) = cond {
+                if !self.condition(cond, loc, "for")? {
+                    break;
+                }
+            }
+            match self.execute(body)? {
+                Flow::Break => break,
+                Flow::Return(val) => return Ok(Flow::Return(val)),
+                Flow::Normal | Flow::Continue => (),
+            }
+            if let Some(increment) = increment {
+                self.interpret_expr(increment)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    //runs the statements in the given scope, restoring the current one afterwards,
+    //a break, continue or return stops the block and is passed on
+    fn execute_block(&mut self, stmts: &[Stmt], env: Environment) -> Result<Flow, RuntimeError> {
+        let previous = std::mem::replace(&mut self.env, Rc::new(RefCell::new(env)));
+        let mut result = Ok(Flow::Normal);
+        for stmt in stmts {
+            result = self.execute(stmt);
+            if !matches!(result, Ok(Flow::Normal)) {
+                break;
             }
         }
+        self.env = previous;
+        result
+    }
+
+    //evaluates a single expression, used by the repl to echo values
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        self.interpret_expr(expr)
     }
 
-    fn interpret_expr(&mut self, expr: &Expr) -> Result<Value, String> {
+    fn interpret_expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
         match expr {
             Expr::Literal(lit) => Ok(self.interpret_literal(lit)),
             Expr::Grouping(e) => self.interpret_expr(e),
             Expr::Unary(op, e) => self.interpret_unary(*op, e),
             Expr::Binary(left, op, right) => self.interpret_binary(*op, left, right),
-            Expr::Ternary(left, middle, right) => todo!(),
-            Expr::Variable(sym) => self.env.get(&sym.name),
-            Expr::Assignment(sym, expr) => {
+            Expr::Logical(left, op, right) => self.interpret_logical(*op, left, right),
+            Expr::Call(callee, loc, args) => self.interpret_call(callee, loc, args),
+            Expr::This(loc, slot) => {
+                let mut sym = this_symbol();
+                sym.line = loc.line;
+                sym.column = loc.col;
+                self.lookup(&sym, slot.get())
+            }
+            Expr::Get(object, name) => {
+                let object = self.interpret_expr(object)?;
+                ops::get_property(&object, &name.name, name.span())
+            }
+            Expr::Set(object, name, value) => {
+                let object = self.interpret_expr(object)?;
+                let val = self.interpret_expr(value)?;
+                ops::set_property(&object, &name.name, val.clone(), name.span())?;
+                Ok(val)
+            }
+            //only the chosen branch is evaluated
+            Expr::Ternary(cond, loc, then_branch, else_branch) => {
+                if self.condition(cond, loc, "?")? {
+                    self.interpret_expr(then_branch)
+                } else {
+                    self.interpret_expr(else_branch)
+                }
+            }
+            Expr::List(elements) => {
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.interpret_expr(element)?);
+                }
+                Ok(Value::List(Rc::new(RefCell::new(values))))
+            }
+            Expr::Map(loc, entries) => {
+                let mut map = Map::default();
+                for (key, value) in entries {
+                    let key = self.interpret_expr(key)?;
+                    let val = self.interpret_expr(value)?;
+                    map.insert(ops::map_key(&key, loc.span(1))?, val);
+                }
+                Ok(Value::Map(Rc::new(RefCell::new(map))))
+            }
+            Expr::Index(object, loc, index) => {
+                let object = self.interpret_expr(object)?;
+                let index = self.interpret_expr(index)?;
+                ops::get_index(&object, &index, loc.span(1))
+            }
+            Expr::SetIndex(object, loc, index, value) => {
+                let object = self.interpret_expr(object)?;
+                let index = self.interpret_expr(index)?;
+                let val = self.interpret_expr(value)?;
+                ops::set_index(&object, &index, val.clone(), loc.span(1))?;
+                Ok(val)
+            }
+            Expr::Variable(sym, slot) => self.lookup(sym, slot.get()),
+            Expr::Assignment(sym, expr, slot) => {
                 let val = self.interpret_expr(expr)?;
-                self.env.assign(sym.clone(), &val)?;
+                match slot.get() {
+                    Some(slot) => Environment::assign_at(&self.env, slot, &val),
+                    None => self.globals.assign(&sym.name, &val, sym.span())?,
+                }
                 Ok(val)
             }
         }
@@ -119,23 +1264,16 @@ impl Interpreter {
     fn interpret_literal(&self, lit: &Literal) -> Value {
         match lit {
             Literal::Number(n) => Value::Number(*n),
-            Literal::String(s) => Value::String(s.clone()),
+            Literal::String(s) => s.as_str().into(),
             Literal::True => Value::Bool(true),
             Literal::False => Value::Bool(false),
             Literal::Null => Value::Null,
         }
     }
 
-    fn interpret_unary(&mut self, op: exprstmt::UnaryOp, e: &Expr) -> Result<Value, String> {
+    fn interpret_unary(&mut self, op: exprstmt::UnaryOp, e: &Expr) -> Result<Value, RuntimeError> {
         let val = self.interpret_expr(e)?;
-
-        match (op.u_type, &val) {
-            (UniOpType::Minus, Value::Number(n)) => Ok(Value::Number(-n)),
-            (UniOpType::Bang, Value::Bool(b)) => Ok(Value::Bool(!b)),
-            (UniOpType::Minus, _) => Err("NaN".to_string()),
-            (UniOpType::Bang, _) => Err("Not a boolean".to_string()),
-            // to do more errorable options
-        }
+        ops::unary(op.u_type, &val, op.span())
     }
 
     fn interpret_binary(
@@ -143,49 +1281,129 @@ impl Interpreter {
         op: exprstmt::BinaryOp,
         left: &Expr,
         right: &Expr,
-    ) -> Result<Value, String> {
+    ) -> Result<Value, RuntimeError> {
         let l = self.interpret_expr(left)?;
         let r = self.interpret_expr(right)?;
+        ops::binary(op.b_type, &l, &r, op.span())
+    }
 
-        match (&l, op.b_type, &r) {
-            (Value::Number(l), BinOpType::Less, Value::Number(r)) => Ok(Value::Bool(l < r)),
-            (Value::Number(l), BinOpType::LessEqual, Value::Number(r)) => Ok(Value::Bool(l <= r)),
-            (Value::Number(l), BinOpType::Greater, Value::Number(r)) => Ok(Value::Bool(l > r)),
-            (Value::Number(l), BinOpType::GreaterEqual, Value::Number(r)) => {
-                Ok(Value::Bool(l >= r))
+    fn interpret_call(
+        &mut self,
+        callee: &Expr,
+        loc: &SourceLocation,
+        args: &[Expr],
+    ) -> Result<Value, RuntimeError> {
+        let callee = self.interpret_expr(callee)?;
+        let mut values = Vec::with_capacity(args.len());
+        for arg in args {
+            values.push(self.interpret_expr(arg)?);
+        }
+
+        match callee {
+            Value::Function(func) => self.call_function(&func, values, loc),
+            Value::Native(func) => ops::call_native(func.as_ref(), values, loc.span(1)),
+            Value::Class(class) => self.instantiate(class, values, loc),
+            _ => Err(ops::not_callable(&callee, loc.span(1))),
+        }
+    }
+
+    //runs the body in a new scope inside the function's closure
+    fn call_function(
+        &mut self,
+        func: &Function,
+        args: Vec<Value>,
+        loc: &SourceLocation,
+    ) -> Result<Value, RuntimeError> {
+        let decl = &func.decl;
+        if args.len() != decl.params.len() {
+            return Err(ops::arity_mismatch(
+                &decl.name.name,
+                decl.params.len(),
+                args.len(),
+                loc.span(1),
+            ));
+        }
+        let stack_use = self.stack_base.saturating_sub(stack_address());
+        if self.call_depth >= MAX_CALL_DEPTH || stack_use > MAX_STACK_USE {
+            return Err(ops::stack_overflow(&decl.name.name, loc.span(1)));
+        }
+
+        let mut env = Environment::new_enclosed(func.closure.clone());
+        for arg in args {
+            env.define(Some(arg));
+        }
+
+        self.call_depth += 1;
+        let result = self.execute_block(&decl.body, env);
+        self.call_depth -= 1;
+
+        match result? {
+            //init gives back the instance, even on an early return
+            //bind put the instance in the first slot of the closure
+            _ if func.is_initializer => {
+                let this = Slot { depth: 0, index: 0 };
+                Environment::get_at(&func.closure, this, &this_symbol())
             }
-            (Value::Number(l), BinOpType::Sub, Value::Number(r)) => Ok(Value::Number(l - r)),
-            (Value::Number(l), BinOpType::Add, Value::Number(r)) => Ok(Value::Number(l + r)),
-            (Value::Number(l), BinOpType::Mult, Value::Number(r)) => Ok(Value::Number(l * r)),
-            (Value::Number(l), BinOpType::Div, Value::Number(r)) => {
-                if *r == 0.0 {
-                    Err(format!(
-                        "[line: {} Column: {}] Can't divide by zero",
-                        op.line, op.column,
-                    ))
-                } else {
-                    Ok(Value::Number(l / r))
-                }
+            Flow::Return(val) => Ok(val),
+            _ => Ok(Value::Null),
+        }
+    }
+
+    //calling a class creates an instance and runs init on it, if there is one
+    fn instantiate(
+        &mut self,
+        class: Rc<Class>,
+        args: Vec<Value>,
+        loc: &SourceLocation,
+    ) -> Result<Value, RuntimeError> {
+        let instance = Rc::new(RefCell::new(Instance {
+            class: class.clone(),
+            fields: HashMap::new(),
+        }));
+
+        match class.methods.get("init") {
+            Some(Value::Function(init)) => {
+                self.call_function(&init.bind(instance.clone()), args, loc)?;
             }
-            (Value::String(l), BinOpType::Add, Value::String(r)) => {
-                Ok(Value::String(format!("{l}{r}")))
+            _ if !args.is_empty() => {
+                return Err(ops::arity_mismatch(&class.name, 0, args.len(), loc.span(1)));
             }
-            (_, BinOpType::EqualEqual, _) => Ok(Value::Bool(Interpreter::equals(&l, &r))),
-            (_, BinOpType::NotEqual, _) => Ok(Value::Bool(Interpreter::equals(&l, &r))),
+            _ => (),
+        }
+        Ok(Value::Instance(instance))
+    }
+
+    //short-circuits, the right side is only evaluated when it decides the result
+    fn interpret_logical(
+        &mut self,
+        op: exprstmt::LogicalOp,
+        left: &Expr,
+        right: &Expr,
+    ) -> Result<Value, RuntimeError> {
+        let l = self.logical_operand(op, left)?;
 
-            _ => Err(todo!()),
+        match (op.l_type, l) {
+            (LogicOpType::Or, true) => Ok(Value::Bool(true)),
+            (LogicOpType::And, false) => Ok(Value::Bool(false)),
+            _ => Ok(Value::Bool(self.logical_operand(op, right)?)),
         }
     }
 
+    fn logical_operand(&mut self, op: exprstmt::LogicalOp, e: &Expr) -> Result<bool, RuntimeError> {
+        let val = self.interpret_expr(e)?;
+        ops::logical_operand(op.l_type, &val, op.span())
+    }
+
     // helper functions
 
-    fn equals(left: &Value, right: &Value) -> bool {
-        match (left, right) {
-            (Value::Number(n1), Value::Number(n2)) => (n1 - n2).abs() < f64::EPSILON,
-            (Value::String(s1), Value::String(s2)) => s1 == s2,
-            (Value::Bool(b1), Value::Bool(b2)) => b1 == b2,
-            (Value::Null, Value::Null) => true,
-            (_, _) => false,
-        }
+    //evaluates the condition of an if, loop or '?', which has to be a boolean
+    fn condition(
+        &mut self,
+        cond: &Expr,
+        loc: &SourceLocation,
+        keyword: &str,
+    ) -> Result<bool, RuntimeError> {
+        let val = self.interpret_expr(cond)?;
+        ops::condition(keyword, &val, loc.span(keyword.len()))
     }
 }
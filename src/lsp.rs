@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::diagnostic::{Diagnostic, Severity, Span};
+use crate::parser;
+use crate::parser::exprstmt::{Stmt, Symbol};
+use crate::resolver::{self, Reference};
+use crate::scanner;
+use crate::utils::json::Json;
+
+//a language server for editors, speaking json-rpc over stdin and stdout,
+//each document is scanned, parsed and resolved again whenever it changes
+
+//error codes from the json-rpc spec
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+//symbol kinds from the lsp spec
+const SYMBOL_CLASS: usize = 5;
+const SYMBOL_METHOD: usize = 6;
+const SYMBOL_CONSTRUCTOR: usize = 9;
+const SYMBOL_FUNCTION: usize = 12;
+const SYMBOL_VARIABLE: usize = 13;
+
+//serves one editor until it sends exit, returning the exit code,
+//which is only 0 if the editor asked the server to shut down first
+pub fn run() -> io::Result<i32> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut server = Server {
+        documents: HashMap::new(),
+        shutdown: false,
+        out: io::stdout(),
+    };
+
+    while let Some(body) = read_message(&mut input)? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                server.send(&error_response(Json::Null, PARSE_ERROR, e))?;
+                continue;
+            }
+        };
+        if message.get("method").and_then(Json::as_str) == Some("exit") {
+            return Ok(if server.shutdown { 0 } else { 1 });
+        }
+        server.handle(&message)?;
+    }
+
+    //the editor went away without sending exit
+    Ok(1)
+}
+
+//a message is a few headers, a blank line and then Content-Length bytes of json,
+//None at the end of the input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let mut body = vec![0; length.unwrap_or(0)];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn error_response(id: Json, code: i64, message: String) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Json::object([("code", code.into()), ("message", message.into())]),
+        ),
+    ])
+}
+
+//what a request failed with, sent back in place of a result
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+impl ResponseError {
+    fn new(code: i64, message: impl Into<String>) -> ResponseError {
+        ResponseError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+struct Server {
+    //the open documents by uri
+    documents: HashMap<String, Document>,
+    //after shutdown every request but exit is an error
+    shutdown: bool,
+    out: io::Stdout,
+}
+
+impl Server {
+    fn handle(&mut self, message: &Json) -> io::Result<()> {
+        //responses to requests from the server have no method, but it never sends any
+        let Some(method) = message.get("method").and_then(Json::as_str) else {
+            return Ok(());
+        };
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let Some(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+
+        let result = if self.shutdown {
+            Err(ResponseError::new(
+                INVALID_REQUEST,
+                "the server is shutting down",
+            ))
+        } else {
+            self.request(method, params)
+        };
+        let response = match result {
+            Ok(result) => Json::object([
+                ("jsonrpc", "2.0".into()),
+                ("id", id.clone()),
+                ("result", result),
+            ]),
+            Err(e) => error_response(id.clone(), e.code, e.message),
+        };
+        self.send(&response)
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, ResponseError> {
+        match method {
+            "initialize" => Ok(Json::object([
+                (
+                    "capabilities",
+                    Json::object([
+                        //the whole text is sent on every change
+                        ("textDocumentSync", 1usize.into()),
+                        ("hoverProvider", true.into()),
+                        ("definitionProvider", true.into()),
+                        ("documentSymbolProvider", true.into()),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    Json::object([
+                        ("name", "apprentice".into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ])),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Json::Null)
+            }
+            "textDocument/hover" => {
+                let (_, document) = self.document(params)?;
+                let (line, column) = document.position_param(params)?;
+                Ok(document.hover(line, column).unwrap_or(Json::Null))
+            }
+            "textDocument/definition" => {
+                let (uri, document) = self.document(params)?;
+                let (line, column) = document.position_param(params)?;
+                Ok(match document.definition(line, column) {
+                    Some((_, declared)) => {
+                        Json::object([("uri", uri.into()), ("range", document.range(declared))])
+                    }
+                    None => Json::Null,
+                })
+            }
+            "textDocument/documentSymbol" => {
+                let (_, document) = self.document(params)?;
+                Ok(Json::Array(document.symbols()))
+            }
+            _ => Err(ResponseError::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method '{method}'"),
+            )),
+        }
+    }
+
+    //notifications get no response, so ones with missing fields are ignored
+    fn notification(&mut self, method: &str, params: &Json) -> io::Result<()> {
+        let document = params.get("textDocument");
+        let Some(uri) = document.and_then(|d| d.get("uri")).and_then(Json::as_str) else {
+            return Ok(());
+        };
+        let uri = uri.to_string();
+
+        let text = match method {
+            "textDocument/didOpen" => document.and_then(|d| d.get("text")),
+            //with full sync the last change has the whole text
+            "textDocument/didChange" => params
+                .get("contentChanges")
+                .and_then(Json::as_array)
+                .and_then(|changes| changes.last())
+                .and_then(|change| change.get("text")),
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return self.publish_diagnostics(&uri, vec![]);
+            }
+            _ => None,
+        };
+        let Some(text) = text.and_then(Json::as_str) else {
+            return Ok(());
+        };
+
+        let document = Document::new(text.to_string());
+        let diagnostics = document
+            .analysis
+            .diagnostics
+            .iter()
+            .map(|d| document.diagnostic(&uri, d))
+            .collect();
+        self.documents.insert(uri.clone(), document);
+        self.publish_diagnostics(&uri, diagnostics)
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str, diagnostics: Vec<Json>) -> io::Result<()> {
+        self.send(&Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            (
+                "params",
+                Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]),
+            ),
+        ]))
+    }
+
+    //the uri and document a request is about
+    fn document<'a>(&'a self, params: &'a Json) -> Result<(&'a str, &'a Document), ResponseError> {
+        let uri = params
+            .get("textDocument")
+            .and_then(|d| d.get("uri"))
+            .and_then(Json::as_str)
+            .ok_or_else(|| ResponseError::new(INVALID_PARAMS, "missing textDocument.uri"))?;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| ResponseError::new(INVALID_PARAMS, format!("'{uri}' is not open")))?;
+        Ok((uri, document))
+    }
+
+    fn send(&mut self, message: &Json) -> io::Result<()> {
+        let body = message.to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.out.flush()
+    }
+}
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+struct Analysis {
+    //like the cli, only the first phase with errors is reported
+    diagnostics: Vec<Diagnostic>,
+    //the statements that parsed, so navigation still works while there are errors
+    stmts: Vec<Stmt>,
+    references: Vec<Reference>,
+}
+
+impl Document {
+    fn new(text: String) -> Document {
+        let (tokens, scan_errors) = scanner::scan(text.clone());
+        let (stmts, parse_errors) = parser::parse(tokens);
+        let (resolve_errors, references) = resolver::resolve_references(&stmts);
+
+        let diagnostics = if !scan_errors.is_empty() {
+            scan_errors.iter().map(|e| e.to_diagnostic()).collect()
+        } else if !parse_errors.is_empty() {
+            parse_errors.iter().map(|e| e.to_diagnostic()).collect()
+        } else {
+            resolve_errors.iter().map(|e| e.to_diagnostic()).collect()
+        };
+
+        Document {
+            text,
+            analysis: Analysis {
+                diagnostics,
+                stmts,
+                references,
+            },
+        }
+    }
+
+    //the variable at the line and column, and where it is declared
+    fn definition(&self, line: usize, column: i64) -> Option<(&Reference, Span)> {
+        let reference = self.analysis.references.iter().find(|r| {
+            r.span.line == line
+                && r.span.column <= column
+                && column <= r.span.column + r.span.len as i64
+        })?;
+        let declared = reference
+            .declared
+            .or_else(|| self.global(&reference.name))?;
+        Some((reference, declared))
+    }
+
+    //top-level declarations are globals, so functions can use ones declared after them
+    fn global(&self, name: &str) -> Option<Span> {
+        self.analysis.stmts.iter().find_map(|stmt| {
+            let sym = match stmt {
+                Stmt::VarDeclaration(sym, _) => sym,
+                Stmt::Function(decl) => &decl.name,
+                Stmt::Class(decl) => &decl.name,
+                _ => return None,
+            };
+            (sym.name == name).then(|| sym.span())
+        })
+    }
+
+    //shows the line the variable is declared on
+    fn hover(&self, line: usize, column: i64) -> Option<Json> {
+        let (reference, declared) = self.definition(line, column)?;
+        let declaration = self.text.lines().nth(declared.line - 1).unwrap_or("");
+        let value = format!(
+            "```apprentice\n{}\n```\n`{}` is declared at line {}, column {}",
+            declaration.trim(),
+            reference.name,
+            declared.line,
+            declared.column
+        );
+        Some(Json::object([
+            (
+                "contents",
+                Json::object([("kind", "markdown".into()), ("value", value.into())]),
+            ),
+            ("range", self.range(reference.span)),
+        ]))
+    }
+
+    //the top-level declarations, with the methods of classes under them
+    fn symbols(&self) -> Vec<Json> {
+        self.analysis
+            .stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::VarDeclaration(sym, _) => Some(self.symbol(sym, SYMBOL_VARIABLE, vec![])),
+                Stmt::Function(decl) => Some(self.symbol(&decl.name, SYMBOL_FUNCTION, vec![])),
+                Stmt::Class(decl) => {
+                    let methods = decl
+                        .methods
+                        .iter()
+                        .map(|method| {
+                            let kind = if method.name.name == "init" {
+                                SYMBOL_CONSTRUCTOR
+                            } else {
+                                SYMBOL_METHOD
+                            };
+                            self.symbol(&method.name, kind, vec![])
+                        })
+                        .collect();
+                    Some(self.symbol(&decl.name, SYMBOL_CLASS, methods))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    //only the name's position is known, so it is the range of the whole symbol too
+    fn symbol(&self, sym: &Symbol, kind: usize, children: Vec<Json>) -> Json {
+        let range = self.range(sym.span());
+        Json::object([
+            ("name", sym.name.as_str().into()),
+            ("kind", kind.into()),
+            ("range", range.clone()),
+            ("selectionRange", range),
+            ("children", children.into()),
+        ])
+    }
+
+    //the notes go at the end of the message and the labels become related information
+    fn diagnostic(&self, uri: &str, diagnostic: &Diagnostic) -> Json {
+        let severity: usize = match diagnostic.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+            Severity::Note => 3,
+        };
+        let mut message = diagnostic.message.clone();
+        for note in &diagnostic.notes {
+            message += &format!("\nnote: {note}");
+        }
+        let related: Vec<Json> = diagnostic
+            .labels
+            .iter()
+            .map(|label| {
+                Json::object([
+                    (
+                        "location",
+                        Json::object([("uri", uri.into()), ("range", self.range(label.span))]),
+                    ),
+                    ("message", label.message.as_str().into()),
+                ])
+            })
+            .collect();
+
+        Json::object([
+            ("range", self.range(diagnostic.span)),
+            ("severity", severity.into()),
+            ("code", diagnostic.code.into()),
+            ("source", "apprentice".into()),
+            ("message", message.into()),
+            ("relatedInformation", related.into()),
+        ])
+    }
+
+    fn range(&self, span: Span) -> Json {
+        Json::object([
+            ("start", self.position(span.line, span.column)),
+            (
+                "end",
+                self.position(span.line, span.column + span.len as i64),
+            ),
+        ])
+    }
+
+    //lsp positions count lines and utf-16 code units from 0, spans count lines and characters from 1
+    fn position(&self, line: usize, column: i64) -> Json {
+        let text = self.line(line);
+        let character: usize = text
+            .chars()
+            .take((column - 1).max(0) as usize)
+            .map(char::len_utf16)
+            .sum();
+        Json::object([
+            ("line", line.saturating_sub(1).into()),
+            ("character", character.into()),
+        ])
+    }
+
+    //the line and column of the position in a request's params
+    fn position_param(&self, params: &Json) -> Result<(usize, i64), ResponseError> {
+        let position = params.get("position");
+        let number = |key| position.and_then(|p| p.get(key)).and_then(Json::as_f64);
+        let (Some(line), Some(character)) = (number("line"), number("character")) else {
+            return Err(ResponseError::new(INVALID_PARAMS, "missing position"));
+        };
+
+        let line = line as usize + 1;
+        let mut units = 0;
+        let mut column = 1;
+        for c in self.line(line).chars() {
+            if units >= character as usize {
+                break;
+            }
+            units += c.len_utf16();
+            column += 1;
+        }
+        Ok((line, column))
+    }
+
+    fn line(&self, line: usize) -> &str {
+        line.checked_sub(1)
+            .and_then(|i| self.text.lines().nth(i))
+            .unwrap_or("")
+    }
+}
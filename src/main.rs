@@ -1,75 +1,212 @@
+use crate::cli::{AstFormat, Backend, Command, Options, OutputFormat};
+use crate::diagnostic::Diagnostic;
 use crate::scanner::token::Token;
-// use crate::utils::*;
-use std::fs;
+use crate::utils::*;
+use std::io::{self, Read};
+use std::{env, fs, process, thread};
 
+mod cli;
+mod compiler;
+mod cst;
+pub mod diagnostic;
+mod formatter;
 mod interpreter;
+mod lsp;
 mod parser;
+mod repl;
+mod resolver;
 mod scanner;
+mod stdlib;
 pub mod utils;
+mod vm;
+
+//exit codes for the different kinds of failure
+const EXIT_UNFORMATTED: i32 = 1;
+const EXIT_USAGE: i32 = 64;
+const EXIT_SCAN_ERROR: i32 = 65;
+const EXIT_PARSE_ERROR: i32 = 66;
+const EXIT_RUNTIME_ERROR: i32 = 70;
+const EXIT_IO_ERROR: i32 = 74;
 
 fn main() {
-    run(String::from("test"));
+    let main = thread::Builder::new()
+        .name("main".to_string())
+        .stack_size(interpreter::STACK_SIZE)
+        .spawn(start)
+        .expect("can't start the main thread");
+    //a panic has already been printed, 101 is what rust exits with after one
+    if main.join().is_err() {
+        process::exit(101);
+    }
 }
 
-fn run(source: String) {
-    let mut had_error = false;
-    //temporary way of creating the scanner
-    let code = fs::read_to_string(format!("src/tests/{}.aprn", source)).expect("can't read file");
-    let scan_result = scanner::scan(code);
+fn start() {
+    let options = match cli::parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {e}\n\n{}", cli::USAGE);
+            process::exit(EXIT_USAGE);
+        }
+    };
+
+    if options.command == Command::Help {
+        println!("{}", cli::USAGE);
+        return;
+    }
 
-    let mut tokens: Vec<Token> = Vec::new();
-    if let Err(e) = scan_result {
-        had_error = true;
-        error(e.line, e.column, e.message.as_str());
+    if options.command == Command::Repl {
+        if let Err(e) = repl::run() {
+            eprintln!("error: {e}");
+            process::exit(EXIT_IO_ERROR);
+        }
+        return;
+    }
+
+    if options.command == Command::Lsp {
+        match lsp::run() {
+            Ok(code) => process::exit(code),
+            Err(e) => {
+                eprintln!("error: {e}");
+                process::exit(EXIT_IO_ERROR);
+            }
+        }
+    }
+
+    let source = match read_source(&options.path) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("error: can't read '{}': {e}", options.path);
+            process::exit(EXIT_IO_ERROR);
+        }
+    };
+
+    if let Err(code) = run(source, &options) {
+        process::exit(code);
+    }
+}
+
+//reads the program from a file, or from stdin if the path is "-"
+fn read_source(path: &str) -> io::Result<String> {
+    if path == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+        Ok(source)
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+//runs every phase on the source, returning the exit code of the phase that failed
+fn run(source: String, options: &Options) -> Result<(), i32> {
+    let file_name = if options.path == "-" {
+        "<stdin>"
     } else {
-        tokens = scan_result.unwrap();
+        options.path.as_str()
+    };
+
+    let (tokens, errors): (Vec<Token>, _) = scanner::scan(source.clone());
+
+    //the tokens are dumped even with errors, showing where the Error tokens ended up
+    if options.dump_tokens {
+        match options.format {
+            OutputFormat::Text => print_token::pr(&tokens),
+            OutputFormat::Json => print_token::pr_json(&tokens),
+        }
     }
 
-    // print_token::pr(&tokens);
+    if !errors.is_empty() {
+        for e in &errors {
+            report(&e.to_diagnostic(), &source, file_name, options.format);
+        }
+        return Err(EXIT_SCAN_ERROR);
+    }
+
+    let (cst, errors) = parser::parse_cst(tokens);
 
-    let parse_result = parser::parse(tokens);
+    //like the tokens, the cst is dumped even with errors, showing what went in Error nodes
+    if options.dump_ast == Some(AstFormat::Cst) {
+        print!("{}", cst::dump(&cst));
+    }
 
-    let ast = match parse_result {
-        Ok(stmts) => {
-            stmts
-            //print_ast::pr(todo!());
+    if !errors.is_empty() {
+        for err in &errors {
+            report(&err.to_diagnostic(), &source, file_name, options.format);
         }
-        Err(err) => {
-            had_error = true;
-            println!("{err:?}");
-            vec![]
+        return Err(EXIT_PARSE_ERROR);
+    }
+
+    let ast = cst::lower(&cst);
+
+    //the formatter takes the comments from the tokens of the cst
+    if options.command == Command::Fmt {
+        let tokens = cst.all_tokens().into_iter().cloned().collect();
+        return fmt(&source, formatter::format(&ast, tokens), options, file_name);
+    }
+
+    //scope errors are static too, so they share the parse error exit code
+    let errors = resolver::resolve(&ast);
+    if !errors.is_empty() {
+        for err in &errors {
+            report(&err.to_diagnostic(), &source, file_name, options.format);
         }
-    };
+        return Err(EXIT_PARSE_ERROR);
+    }
 
-    if had_error {
-        return;
+    match options.dump_ast {
+        Some(AstFormat::Sexpr) => print_ast::pr(&ast),
+        Some(AstFormat::Tree) => print_ast::pr_tree(&ast),
+        Some(AstFormat::Cst) | None => (),
+    }
+
+    if options.command == Command::Check {
+        return Ok(());
     }
 
-    let runtime_result = interpreter::interpret(&ast);
-    match runtime_result {
-        Ok(_) => return,
-        Err(s) => println!("{s:?}"),
+    let result = match options.backend {
+        Backend::Tree => interpreter::interpret(&ast),
+        //the limits of the bytecode are checked before anything runs
+        Backend::Vm => match compiler::compile(&ast) {
+            Ok(script) => vm::run(script),
+            Err(err) => {
+                report(&err.to_diagnostic(), &source, file_name, options.format);
+                return Err(EXIT_PARSE_ERROR);
+            }
+        },
+    };
+
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            report(&e.to_diagnostic(), &source, file_name, options.format);
+            Err(EXIT_RUNTIME_ERROR)
+        }
     }
 }
 
-fn error(line: usize, column: i64, message: &str) {
-    report(line, column, "", message);
+//writes the formatted program back to the file, or to stdout for stdin,
+//with --check nothing is written and an unformatted file is an error
+fn fmt(source: &str, formatted: String, options: &Options, file_name: &str) -> Result<(), i32> {
+    if options.check {
+        if formatted != source {
+            eprintln!("{file_name} is not formatted");
+            return Err(EXIT_UNFORMATTED);
+        }
+        return Ok(());
+    }
+    if options.path == "-" {
+        print!("{formatted}");
+    } else if formatted != source {
+        if let Err(e) = fs::write(&options.path, formatted) {
+            eprintln!("error: can't write '{}': {e}", options.path);
+            return Err(EXIT_IO_ERROR);
+        }
+    }
+    Ok(())
 }
 
-// fn t_error(token: Token, message: &str) {
-//     if token.token_type == token::TokenType::Eof {
-//         report(token.line, token.column, " at end", message);
-//     } else {
-//         report(
-//             token.line,
-//             token.column,
-//             format!(" at '{}'", String::from_utf8(token.lexeme).unwrap()).as_str(),
-//             message,
-//         );
-//     }
-// }
-
-fn report(line: usize, column: i64, place: &str, message: &str) {
-    panic!("[line: {line}, column: {column}] Error {place}: {message}");
-    //had_error = true;
+fn report(diagnostic: &Diagnostic, source: &str, file_name: &str, format: OutputFormat) {
+    match format {
+        OutputFormat::Text => eprint!("{}", diagnostic.render(source, file_name)),
+        OutputFormat::Json => eprintln!("{}", diagnostic.to_json(file_name)),
+    }
 }
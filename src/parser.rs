@@ -1,10 +1,16 @@
 use std::fmt;
 
-use crate::parser::exprstmt::{Expr, Literal, Stmt, Symbol};
-use crate::scanner::token::{self, Token, TokenType};
+use crate::cst::{self, Node, NodeKind};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::parser::exprstmt::Stmt;
+use crate::scanner::token::{Token, TokenType};
 
 pub mod exprstmt {
+    use std::cell::Cell;
     use std::fmt;
+    use std::rc::Rc;
+
+    use crate::diagnostic::Span;
 
     #[derive(Debug, Eq, PartialEq, Hash, Clone)]
     pub struct Symbol {
@@ -12,27 +18,59 @@ pub mod exprstmt {
         pub line: usize,
         pub column: i64,
     }
+    impl Symbol {
+        pub fn span(&self) -> Span {
+            Span::new(self.line, self.column, self.name.chars().count())
+        }
+    }
+
+    // where a local variable is stored, filled in by the resolver,
+    // variables it leaves as None are globals and are looked up by name
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub struct Slot {
+        // how many scopes out from the one the variable is used in
+        pub depth: usize,
+        // the position in that scope, variables are numbered in the order they are declared
+        pub index: usize,
+    }
 
     // -----------
     // Expressions
     // -----------
     #[derive(Debug, Clone)]
     pub enum Expr {
-        //This(SourceLocation),
+        This(SourceLocation, Cell<Option<Slot>>),
         Literal(Literal),
         Unary(UnaryOp, Box<Expr>),
         Binary(Box<Expr>, BinaryOp, Box<Expr>),
-        Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
-        Assignment(Symbol, Box<Expr>),
+        Logical(Box<Expr>, LogicalOp, Box<Expr>),
+        // the location is the one of the '('
+        Call(Box<Expr>, SourceLocation, Vec<Expr>),
+        Get(Box<Expr>, Symbol),
+        Set(Box<Expr>, Symbol, Box<Expr>),
+        List(Vec<Expr>),
+        // the location is the one of the '{'
+        Map(SourceLocation, Vec<(Expr, Expr)>),
+        // the location is the one of the '['
+        Index(Box<Expr>, SourceLocation, Box<Expr>),
+        SetIndex(Box<Expr>, SourceLocation, Box<Expr>, Box<Expr>),
+        // the location is the one of the '?'
+        Ternary(Box<Expr>, SourceLocation, Box<Expr>, Box<Expr>),
+        Assignment(Symbol, Box<Expr>, Cell<Option<Slot>>),
         Grouping(Box<Expr>),
-        Variable(Symbol),
+        Variable(Symbol, Cell<Option<Slot>>),
     }
 
-    // #[derive(Debug, Copy, Clone)]
-    // pub struct SourceLocation {
-    //     pub line: usize,
-    //     pub col: i64,
-    // }
+    #[derive(Debug, Copy, Clone)]
+    pub struct SourceLocation {
+        pub line: usize,
+        pub col: i64,
+    }
+    impl SourceLocation {
+        pub fn span(&self, len: usize) -> Span {
+            Span::new(self.line, self.col, len)
+        }
+    }
 
     #[derive(Debug, Copy, Clone)]
     pub struct UnaryOp {
@@ -41,6 +79,12 @@ pub mod exprstmt {
         pub column: i64,
     }
 
+    impl UnaryOp {
+        pub fn span(&self) -> Span {
+            Span::new(self.line, self.column, 1)
+        }
+    }
+
     #[derive(Debug, Copy, Clone)]
     pub enum UniOpType {
         Minus,
@@ -62,6 +106,12 @@ pub mod exprstmt {
         pub column: i64,
     }
 
+    impl BinaryOp {
+        pub fn span(&self) -> Span {
+            Span::new(self.line, self.column, self.b_type.to_string().len())
+        }
+    }
+
     #[derive(Debug, Copy, Clone)]
     pub enum BinOpType {
         EqualEqual,
@@ -92,6 +142,33 @@ pub mod exprstmt {
         }
     }
 
+    #[derive(Debug, Copy, Clone)]
+    pub struct LogicalOp {
+        pub l_type: LogicOpType,
+        pub line: usize,
+        pub column: i64,
+    }
+
+    impl LogicalOp {
+        pub fn span(&self) -> Span {
+            Span::new(self.line, self.column, self.l_type.to_string().len())
+        }
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub enum LogicOpType {
+        And,
+        Or,
+    }
+    impl fmt::Display for LogicOpType {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match &self {
+                LogicOpType::And => write!(f, "and"),
+                LogicOpType::Or => write!(f, "or"),
+            }
+        }
+    }
+
     #[derive(Debug, Clone, PartialEq)]
     pub enum Literal {
         Number(f64),
@@ -121,6 +198,38 @@ pub mod exprstmt {
         Expression(Expr),
         Print(Expr),
         VarDeclaration(Symbol, Option<Expr>),
+        Block(Vec<Stmt>),
+        If(SourceLocation, Expr, Box<Stmt>, Option<Box<Stmt>>),
+        While(SourceLocation, Expr, Box<Stmt>),
+        For(
+            SourceLocation,
+            Option<Box<Stmt>>,
+            Option<Expr>,
+            Option<Expr>,
+            Box<Stmt>,
+        ),
+        Break,
+        Continue,
+        Function(Rc<FunctionDecl>),
+        Return(Option<Expr>),
+        Class(Rc<ClassDecl>),
+        Throw(SourceLocation, Expr),
+        // the body, the name the caught error is bound to and the handler
+        Try(Vec<Stmt>, Symbol, Vec<Stmt>),
+    }
+
+    // shared with the function values created from it
+    #[derive(Debug)]
+    pub struct FunctionDecl {
+        pub name: Symbol,
+        pub params: Vec<Symbol>,
+        pub body: Vec<Stmt>,
+    }
+
+    #[derive(Debug)]
+    pub struct ClassDecl {
+        pub name: Symbol,
+        pub methods: Vec<Rc<FunctionDecl>>,
     }
 }
 
@@ -128,33 +237,80 @@ pub mod exprstmt {
 struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    errors: Vec<SyntaxError>,
+    //how many blocks deep the parser is, used when recovering from errors
+    block_depth: usize,
+    //how many loops deep the parser is, break and continue need at least one
+    loop_depth: usize,
+    //the kind of function being parsed, return needs to be in one
+    function_kind: FunctionKind,
+    //how many classes deep the parser is, this needs at least one
+    class_depth: usize,
+    //how many expressions and statements deep the parser is, see nested
+    nesting: usize,
+    //set once the nesting got too deep, the rest of the file is skipped
+    too_deep: bool,
+    builder: cst::Builder,
+}
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    #[default]
+    None,
+    Function,
+    Method,
+    Initializer,
 }
 
 pub enum SyntaxError {
-    UnexpectedToken(Token),
+    UnexpectedToken(Box<Token>),
     TokenMismatch {
         expected: TokenType,
-        found: Token,
+        found: Box<Token>,
         maybe_err: Option<String>,
+        // the token the expected one should have followed, when the error is shown after it
+        after: Option<Box<Token>>,
     },
-    InvalidTokenInBinaryOp {
-        token_type: TokenType,
+    ExpectedExpression {
+        // an expression was expected, found is the token in its place
+        found: Box<Token>,
+        // set at the end of the file, the error is shown after this token
+        after: Option<Box<Token>>,
+    },
+    InvalidAssignment {
+        // the assignment target is invalid
         line: usize,
         column: i64,
     },
-    InvalidTokenInUnaryOp {
+    OutsideLoop {
+        // break or continue outside of a loop
         token_type: TokenType,
         line: usize,
         column: i64,
     },
-    ExpectedExpression {
-        // an expression token was expected
-        token_type: TokenType,
+    OutsideFunction {
+        // return outside of a function
         line: usize,
         column: i64,
     },
-    InvalidAssignment {
-        // the assignment target is invalid
+    OutsideClass {
+        // this outside of a class
+        line: usize,
+        column: i64,
+    },
+    ReturnFromInitializer {
+        // init always returns the new instance
+        line: usize,
+        column: i64,
+    },
+    MissingTernaryColon {
+        // the ':' of a ternary, opened by the '?' at line and column, is missing
+        found: Box<Token>,
+        line: usize,
+        column: i64,
+    },
+    TooDeep {
+        // expressions or statements nest past MAX_NESTING at this token
         line: usize,
         column: i64,
     },
@@ -172,6 +328,7 @@ impl fmt::Debug for SyntaxError {
                 expected,
                 found,
                 maybe_err,
+                ..
             } => {
                 write!(
                     f,
@@ -183,44 +340,182 @@ impl fmt::Debug for SyntaxError {
                 }
                 fmt::Result::Ok(())
             }
-            SyntaxError::InvalidTokenInBinaryOp {
+            SyntaxError::ExpectedExpression { found, .. } => write!(
+                f,
+                "[line: {}, Column: {}] Expected Expression, found {:?}",
+                found.line, found.column, found.token_type,
+            ),
+            SyntaxError::InvalidAssignment { line, column } => {
+                write!(
+                    f,
+                    "[line: {}, Column: {}] Assignment target is Invalide",
+                    line, column
+                )
+            }
+            SyntaxError::OutsideLoop {
                 token_type,
                 line,
                 column,
             } => write!(
                 f,
-                "[line: {}, Column: {}] Invalid Binary Operator: {:?}",
+                "[line: {}, Column: {}] {:?} can only be used inside a loop",
                 line, column, token_type
             ),
-            SyntaxError::InvalidTokenInUnaryOp {
-                token_type,
+            SyntaxError::OutsideFunction { line, column } => write!(
+                f,
+                "[line: {}, Column: {}] Return can only be used inside a function",
+                line, column
+            ),
+            SyntaxError::OutsideClass { line, column } => write!(
+                f,
+                "[line: {}, Column: {}] This can only be used inside a class",
+                line, column
+            ),
+            SyntaxError::ReturnFromInitializer { line, column } => write!(
+                f,
+                "[line: {}, Column: {}] Can't return a value from init",
+                line, column
+            ),
+            SyntaxError::MissingTernaryColon {
+                found,
                 line,
                 column,
             } => write!(
                 f,
-                "[line: {}, Column: {}] Invalid Unary Operator: {:?}",
-                line, column, token_type
+                "[line: {}, Column: {}] Expected ':' for the '?' at [line: {}, Column: {}] but found {:?}",
+                found.line, found.column, line, column, found.token_type
+            ),
+            SyntaxError::TooDeep { line, column } => write!(
+                f,
+                "[line: {}, Column: {}] Nested too deeply",
+                line, column
             ),
-            SyntaxError::ExpectedExpression {
+        }
+    }
+}
+
+impl SyntaxError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        match &self {
+            SyntaxError::UnexpectedToken(token) => {
+                let span = token_span(token);
+                Diagnostic::error(
+                    "E0101",
+                    format!("Unexpected {} found for this place", describe(token)),
+                    span,
+                )
+                .with_label(span, "not expected here")
+            }
+            SyntaxError::TokenMismatch {
+                expected,
+                found,
+                maybe_err,
+                after,
+            } => {
+                let span = match after {
+                    Some(after) => after_token(after),
+                    None => token_span(found),
+                };
+                let message = match maybe_err {
+                    Some(message) => message.clone(),
+                    None => format!("Expected token {:?}", expected),
+                };
+                Diagnostic::error("E0102", message, span)
+                    .with_label(span, format!("found {}", describe(found)))
+            }
+            SyntaxError::ExpectedExpression { found, after } => {
+                let span = match after {
+                    Some(after) => after_token(after),
+                    None => Span::new(found.line, found.column, 1),
+                };
+                Diagnostic::error("E0105", "Expected expression", span)
+                    .with_label(span, format!("found {}", describe(found)))
+            }
+            SyntaxError::InvalidAssignment { line, column } => Diagnostic::error(
+                "E0106",
+                "Assignment target is invalid",
+                Span::new(*line, *column, 1),
+            )
+            .with_note("only variables can be assigned to"),
+            SyntaxError::OutsideLoop {
                 token_type,
                 line,
                 column,
-            } => write!(
-                f,
-                "[line: {}, Column: {}] Expected Expression, found {:?}",
-                line, column, token_type,
-            ),
-            SyntaxError::InvalidAssignment { line, column } => {
-                write!(
-                    f,
-                    "[line: {}, Column: {}] Assignment target is Invalide",
-                    line, column
+            } => {
+                let keyword = match token_type {
+                    TokenType::Break => "break",
+                    _ => "continue",
+                };
+                Diagnostic::error(
+                    "E0107",
+                    format!("'{keyword}' can only be used inside a loop"),
+                    Span::new(*line, *column, keyword.len()),
                 )
             }
+            SyntaxError::OutsideFunction { line, column } => Diagnostic::error(
+                "E0109",
+                "'return' can only be used inside a function",
+                Span::new(*line, *column, "return".len()),
+            ),
+            SyntaxError::OutsideClass { line, column } => Diagnostic::error(
+                "E0110",
+                "'this' can only be used inside a class",
+                Span::new(*line, *column, "this".len()),
+            ),
+            SyntaxError::ReturnFromInitializer { line, column } => Diagnostic::error(
+                "E0111",
+                "Can't return a value from init",
+                Span::new(*line, *column, "return".len()),
+            )
+            .with_note("init always returns the new instance"),
+            SyntaxError::MissingTernaryColon {
+                found,
+                line,
+                column,
+            } => {
+                let span = token_span(found);
+                Diagnostic::error("E0108", "Expected ':' in conditional expression", span)
+                    .with_label(span, format!("found {}", describe(found)))
+                    .with_label(Span::new(*line, *column, 1), "'?' is here")
+                    .with_note("a conditional looks like: condition ? value : other_value")
+            }
+            SyntaxError::TooDeep { line, column } => Diagnostic::error(
+                "E0112",
+                "Expressions and blocks are nested too deeply",
+                Span::new(*line, *column, 1),
+            )
+            .with_note(format!("they can be nested at most {MAX_NESTING} deep")),
         }
     }
 }
 
+fn token_span(token: &Token) -> Span {
+    let len = String::from_utf8_lossy(&token.lexeme).chars().count();
+    Span::new(token.line, token.column, len.max(1))
+}
+
+// the position right after a token, where a missing ';' belongs
+fn after_token(token: &Token) -> Span {
+    let lexeme = String::from_utf8_lossy(&token.lexeme);
+    match lexeme.rsplit_once('\n') {
+        // a multi-line string ends on a later line
+        Some((before, last)) => Span::new(
+            token.line + before.matches('\n').count() + 1,
+            last.chars().count() as i64 + 1,
+            1,
+        ),
+        None => Span::new(token.line, token.column + lexeme.chars().count() as i64, 1),
+    }
+}
+
+// how a token is named in messages, by its text
+fn describe(token: &Token) -> String {
+    match token.token_type {
+        TokenType::Eof => "end of file".to_string(),
+        _ => format!("'{}'", String::from_utf8_lossy(&token.lexeme)),
+    }
+}
+
 /*
 
 Recursive descent using this grammar
@@ -228,306 +523,667 @@ Recursive descent using this grammar
 program      = declaration* EOF ;
 
 declaration  = varDecl
+             | funcDecl
+             | classDecl
              | statement
 
 varDecl      = "var" IDENTIFIER ( "=" expression )? ";"
+funcDecl     = "func" function
+function     = IDENTIFIER "(" parameters? ")" block
+parameters   = IDENTIFIER ( "," IDENTIFIER )*
+classDecl    = "class" IDENTIFIER "{" function* "}"
 
 statement    = printStmt
+             | block
+             | ifStmt
+             | whileStmt
+             | forStmt
+             | breakStmt
+             | continueStmt
+             | returnStmt
+             | throwStmt
+             | tryStmt
              | exprStmt
 
 printStmt    = "print" expression ";"
-varStmt      = "var"
-funcStmt     = "func"
-classStmt	 = "class"
+block        = "{" declaration* "}"
+ifStmt       = "if" "(" expression ")" statement ( "else" statement )?
+whileStmt    = "while" "(" expression ")" statement
+forStmt      = "for" "(" ( varDecl | exprStmt | ";" )
+               expression? ";" expression? ")" statement
+breakStmt    = "break" ";"
+continueStmt = "continue" ";"
+returnStmt   = "return" expression? ";"
+throwStmt    = "throw" expression ";"
+tryStmt      = "try" block "catch" "(" IDENTIFIER ")" block
 exprStmt     = expression ";" ;
 
-expression   = equality ;
+expression   = assignment ;
+assignment   = ( call "." )? IDENTIFIER "=" assignment
+             | call "[" expression "]" "=" assignment
+             | ternary ;
+ternary      = logic_or ( "?" expression ":" ternary )? ;
+logic_or     = logic_and ( "or" logic_and )* ;
+logic_and    = equality ( "and" equality )* ;
 equality     = comparison ( ( "!=" | "==" ) comparison )* ;
 comparison   = term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
 term         = factor ( ( "-" | "+" ) factor )* ;
 factor       = unary ( ( "/" | "*" ) unary )* ;
 unary        = ( "!" | "-" ) unary
-             | primary ;
-primary      = "true" | "false" | "null"
+             | call ;
+call         = primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]" )* ;
+arguments    = expression ( "," expression )* ;
+primary      = "true" | "false" | "null" | "this"
              | NUMBER | STRING
              | "(" expression ")"
+             | "[" arguments? "]"
+             | "{" ( entry ( "," entry )* )? "}"
              | IDENTIFIER ;
+entry        = expression ":" expression ;
+
+a '{' that starts a statement is always a block, so a map literal can only
+appear where an expression is expected, e.g. var m = {"a": 1};
 
 */
 
-// function that allows external usage of the parser
-pub fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, SyntaxError> {
+// how deep expressions and statements can nest, every level is a few calls deep
+// in the parser and in everything that walks the tree after it
+const MAX_NESTING: usize = 256;
+
+// function that allows external usage of the parser,
+// statements with syntax errors are left out of the returned tree
+pub fn parse(tokens: Vec<Token>) -> (Vec<Stmt>, Vec<SyntaxError>) {
+    let (cst, errors) = parse_cst(tokens);
+    (cst::lower(&cst), errors)
+}
+
+// the lossless tree the ast is derived from,
+// statements with syntax errors are kept in it as Error nodes
+pub fn parse_cst(tokens: Vec<Token>) -> (Node, Vec<SyntaxError>) {
     let mut p = Parser {
         tokens,
         ..Default::default()
     };
-    let parse_result = p.parse();
-
-    match parse_result {
-        Ok(result) => {
-            // should be the end of the file, if it isn't the parser got stuck
-            if !p.is_at_end() {
-                let token = &p.tokens[p.current];
-                Err(SyntaxError::UnexpectedToken(token.clone()))
-            } else {
-                Ok(result)
-            }
-        }
-        Err(err) => Err(err),
-    }
+    p.parse();
+
+    (p.builder.finish(), p.errors)
 }
 
 impl Parser {
-    pub fn parse(&mut self) -> Result<Vec<Stmt>, SyntaxError> {
-        let mut statements: Vec<Stmt> = vec![];
+    pub fn parse(&mut self) {
+        self.builder.start_node(NodeKind::Program);
         while !self.is_at_end() {
-            statements.push(self.declaration()?);
+            self.declaration();
         }
-
-        Ok(statements)
+        // the end of file keeps the trivia after the last statement
+        self.builder.token(self.peek().clone());
+        self.builder.finish_node();
     }
 
-    fn declaration(&mut self) -> Result<Stmt, SyntaxError> {
-        if self.matches(TokenType::Var) {
-            return self.var_declaration();
+    // records the error and skips to the next statement if the declaration is invalid,
+    // what was parsed of it and the skipped tokens go in an Error node
+    fn declaration(&mut self) {
+        let checkpoint = self.builder.checkpoint();
+        let depth = self.builder.depth();
+        let result = if self.check(TokenType::Var) {
+            self.var_declaration()
+        } else if self.check(TokenType::Func) {
+            self.function_declaration()
+        } else if self.check(TokenType::Class) {
+            self.class_declaration()
+        } else {
+            self.statement()
+        };
+
+        if let Err(err) = result {
+            // past a too deep nesting every enclosing block is missing its '}'
+            if !self.too_deep || matches!(err, SyntaxError::TooDeep { .. }) {
+                self.errors.push(err);
+            }
+            self.builder.finish_nodes_to(depth);
+            self.synchronize();
+            self.builder.start_node_at(checkpoint, NodeKind::Error);
+            self.builder.finish_node();
         }
-        // if there's an error, synchronize()
-        self.statement()
     }
 
-    fn var_declaration(&mut self) -> Result<Stmt, SyntaxError> {
-        let name = self
-            .consume(TokenType::Identifier, "Expected variable name.")?
-            .clone();
+    fn var_declaration(&mut self) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::VarDecl);
+        self.advance();
+        self.consume(TokenType::Identifier, "Expected variable name.")?;
 
-        let initializer = if self.matches(TokenType::Equal) {
-            Some(self.expression()?)
-        } else {
-            None
-        };
+        if self.matches(TokenType::Equal) {
+            self.expression()?;
+        }
 
         self.consume(
             TokenType::Semicolon,
             "Expected ';' after variable declaration.",
-        );
-        Ok(Stmt::VarDeclaration(
-            Symbol {
-                name: String::from_utf8(name.lexeme).unwrap(),
-                line: name.line,
-                column: name.column,
-            },
-            initializer,
-        ))
-    }
-
-    fn statement(&mut self) -> Result<Stmt, SyntaxError> {
-        if self.matches(TokenType::Print) {
+        )?;
+        self.builder.finish_node();
+        Ok(())
+    }
+
+    fn function_declaration(&mut self) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::FuncDecl);
+        self.advance();
+        self.function(FunctionKind::Function)?;
+        self.builder.finish_node();
+        Ok(())
+    }
+
+    // the name, parameters and body of a function, after "func"
+    fn function(&mut self, kind: FunctionKind) -> Result<(), SyntaxError> {
+        let name = self.consume(TokenType::Identifier, "Expected function name")?;
+        let kind = if kind == FunctionKind::Method && name.lexeme == b"init" {
+            FunctionKind::Initializer
+        } else {
+            kind
+        };
+
+        self.builder.start_node(NodeKind::ParamList);
+        let params = self.parameters();
+        self.builder.finish_node();
+        if let Err(err) = params {
+            // the body after a broken parameter list is still parsed as the function's,
+            // so it doesn't get errors of its own, like a return outside a function
+            while matches!(
+                self.peek().token_type,
+                TokenType::Identifier | TokenType::Comma | TokenType::RightParen
+            ) {
+                self.advance();
+            }
+            if !self.check(TokenType::LeftCurly) {
+                return Err(err);
+            }
+            self.errors.push(err);
+        }
+
+        // a loop around the function doesn't let its body use break
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let enclosing_kind = std::mem::replace(&mut self.function_kind, kind);
+        let body = self.block("Expected '{' before function body");
+        self.function_kind = enclosing_kind;
+        self.loop_depth = loop_depth;
+        body
+    }
+
+    fn parameters(&mut self) -> Result<(), SyntaxError> {
+        self.consume(TokenType::LeftParen, "Expected '(' after function name")?;
+        if !self.check(TokenType::RightParen) {
+            loop {
+                self.consume(TokenType::Identifier, "Expected parameter name")?;
+                if !self.matches(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expected ')' after parameters")?;
+        Ok(())
+    }
+
+    fn class_declaration(&mut self) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::ClassDecl);
+        self.advance();
+        self.consume(TokenType::Identifier, "Expected class name")?;
+        self.consume(TokenType::LeftCurly, "Expected '{' before class body")?;
+
+        self.class_depth += 1;
+        while !self.check(TokenType::RightCurly) && !self.is_at_end() {
+            self.builder.start_node(NodeKind::Method);
+            if let Err(err) = self.function(FunctionKind::Method) {
+                self.class_depth -= 1;
+                return Err(err);
+            }
+            self.builder.finish_node();
+        }
+        self.class_depth -= 1;
+
+        self.consume(TokenType::RightCurly, "Expected '}' after class body")?;
+        self.builder.finish_node();
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), SyntaxError> {
+        if self.check(TokenType::Print) {
             return self.print_statement();
         }
+        if self.check(TokenType::LeftCurly) {
+            return self.block("Expected '{'");
+        }
+        if self.check(TokenType::If) {
+            return self.if_statement();
+        }
+        if self.check(TokenType::While) {
+            return self.while_statement();
+        }
+        if self.check(TokenType::For) {
+            return self.for_statement();
+        }
+        if self.check(TokenType::Break) || self.check(TokenType::Continue) {
+            return self.loop_jump_statement();
+        }
+        if self.check(TokenType::Return) {
+            return self.return_statement();
+        }
+        if self.check(TokenType::Throw) {
+            return self.throw_statement();
+        }
+        if self.check(TokenType::Try) {
+            return self.try_statement();
+        }
         // else if self.matches(TokenType::Var) {
         //     return declareVariable();
         // }
         self.expression_statement()
     }
 
-    fn print_statement(&mut self) -> Result<Stmt, SyntaxError> {
-        let val = self.expression();
-        self.consume(TokenType::Semicolon, "Expected ';'")?;
-        Ok(Stmt::Print(val.unwrap()))
+    // a block statement or the body of a function, try or catch,
+    // the message is for a missing '{'
+    fn block(&mut self, message: &str) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::Block);
+        self.consume(TokenType::LeftCurly, message)?;
+
+        self.nested(|p| {
+            p.block_depth += 1;
+            while !p.check(TokenType::RightCurly) && !p.is_at_end() {
+                p.declaration();
+            }
+            p.block_depth -= 1;
+            Ok(())
+        })?;
+
+        self.consume(TokenType::RightCurly, "Expected '}' after block")?;
+        self.builder.finish_node();
+        Ok(())
     }
 
-    fn expression_statement(&mut self) -> Result<Stmt, SyntaxError> {
-        let val = self.expression();
-        self.consume(TokenType::Semicolon, "Expected ';'")?;
-        Ok(Stmt::Expression(val.unwrap()))
+    fn if_statement(&mut self) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::IfStmt);
+        self.advance();
+        self.consume(TokenType::LeftParen, "Expected '(' after 'if'")?;
+        self.expression()?;
+        self.consume(TokenType::RightParen, "Expected ')' after if condition")?;
+
+        self.nested(Parser::statement)?;
+        if self.matches(TokenType::Else) {
+            self.nested(Parser::statement)?;
+        }
+
+        self.builder.finish_node();
+        Ok(())
     }
 
-    pub fn expression(&mut self) -> Result<Expr, SyntaxError> {
-        self.assignment()
+    fn while_statement(&mut self) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::WhileStmt);
+        self.advance();
+        self.consume(TokenType::LeftParen, "Expected '(' after 'while'")?;
+        self.expression()?;
+        self.consume(TokenType::RightParen, "Expected ')' after while condition")?;
+
+        self.loop_body()?;
+        self.builder.finish_node();
+        Ok(())
     }
 
-    pub fn assignment(&mut self) -> Result<Expr, SyntaxError> {
-        let expr = self.equality()?;
+    fn for_statement(&mut self) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::ForStmt);
+        self.advance();
+        self.consume(TokenType::LeftParen, "Expected '(' after 'for'")?;
 
-        if self.matches(TokenType::Equal) {
-            let equals = self.previous().clone();
-            let value = self.assignment()?;
+        if self.matches(TokenType::Semicolon) {
+            // no initializer
+        } else if self.check(TokenType::Var) {
+            self.var_declaration()?;
+        } else {
+            self.expression_statement()?;
+        }
 
-            if let Expr::Variable(sym) = &expr {
-                return Ok(Expr::Assignment(sym.clone(), Box::new(value)));
-            } else {
-                return Err(SyntaxError::InvalidAssignment {
-                    line: equals.line,
-                    column: equals.column,
-                });
-            }
+        if !self.check(TokenType::Semicolon) {
+            self.expression()?;
         }
-        Ok(expr)
+        self.consume(TokenType::Semicolon, "Expected ';' after loop condition")?;
+
+        if !self.check(TokenType::RightParen) {
+            self.expression()?;
+        }
+        self.consume(TokenType::RightParen, "Expected ')' after for clauses")?;
+
+        self.loop_body()?;
+        self.builder.finish_node();
+        Ok(())
     }
 
-    pub fn equality(&mut self) -> Result<Expr, SyntaxError> {
-        let mut expr: Expr = self.comparison()?;
+    fn loop_body(&mut self) -> Result<(), SyntaxError> {
+        self.loop_depth += 1;
+        let body = self.nested(Parser::statement);
+        self.loop_depth -= 1;
+        body
+    }
 
-        while self.match_one_of(vec![TokenType::EqualEqual, TokenType::BangEqual]) {
-            let operator: Token = self.previous().clone();
-            let right = Box::new(self.comparison()?);
+    // break and continue
+    fn loop_jump_statement(&mut self) -> Result<(), SyntaxError> {
+        let keyword = self.peek().clone();
+        self.builder
+            .start_node(if keyword.token_type == TokenType::Break {
+                NodeKind::BreakStmt
+            } else {
+                NodeKind::ContinueStmt
+            });
+        self.advance();
+        self.consume(TokenType::Semicolon, "Expected ';'")?;
 
-            let binop_maybe = Parser::op_token_to_binop(&operator);
+        // the statement is complete, so this is reported without needing to synchronize
+        if self.loop_depth == 0 {
+            self.errors.push(SyntaxError::OutsideLoop {
+                token_type: keyword.token_type,
+                line: keyword.line,
+                column: keyword.column,
+            });
+        }
+        self.builder.finish_node();
+        Ok(())
+    }
 
-            match binop_maybe {
-                Ok(binop) => {
-                    let left = Box::new(expr);
-                    expr = Expr::Binary(left, binop, right);
-                }
-                Err(e) => return Err(e),
-            }
+    fn return_statement(&mut self) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::ReturnStmt);
+        self.advance();
+        let location = self.previous_location();
+        let has_value = !self.check(TokenType::Semicolon);
+        if has_value {
+            self.expression()?;
+        }
+        self.consume(TokenType::Semicolon, "Expected ';' after return value")?;
+
+        if self.function_kind == FunctionKind::None {
+            self.errors.push(SyntaxError::OutsideFunction {
+                line: location.line,
+                column: location.col,
+            });
+        } else if self.function_kind == FunctionKind::Initializer && has_value {
+            self.errors.push(SyntaxError::ReturnFromInitializer {
+                line: location.line,
+                column: location.col,
+            });
         }
-        Ok(expr)
+        self.builder.finish_node();
+        Ok(())
     }
 
-    pub fn comparison(&mut self) -> Result<Expr, SyntaxError> {
-        let mut expr: Expr = self.term()?;
+    fn throw_statement(&mut self) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::ThrowStmt);
+        self.advance();
+        self.expression()?;
+        self.consume(TokenType::Semicolon, "Expected ';' after thrown value")?;
+        self.builder.finish_node();
+        Ok(())
+    }
 
-        while self.match_one_of(vec![
-            TokenType::Less,
-            TokenType::LessEqual,
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-        ]) {
-            let operator: Token = self.previous().clone();
-            let right = Box::new(self.term()?);
+    fn try_statement(&mut self) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::TryStmt);
+        self.advance();
+        self.block("Expected '{' after 'try'")?;
 
-            let binop_maybe = Parser::op_token_to_binop(&operator);
+        self.consume(TokenType::Catch, "Expected 'catch' after try block")?;
+        self.consume(TokenType::LeftParen, "Expected '(' after 'catch'")?;
+        self.consume(TokenType::Identifier, "Expected error name")?;
+        self.consume(TokenType::RightParen, "Expected ')' after error name")?;
 
-            match binop_maybe {
-                Ok(binop) => {
-                    let left = Box::new(expr);
-                    expr = Expr::Binary(left, binop, right);
-                }
-                Err(e) => return Err(e),
-            }
-        }
-        Ok(expr)
+        self.block("Expected '{' before catch block")?;
+        self.builder.finish_node();
+        Ok(())
     }
 
-    pub fn term(&mut self) -> Result<Expr, SyntaxError> {
-        let mut expr: Expr = self.factor()?;
+    fn print_statement(&mut self) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::PrintStmt);
+        self.advance();
+        self.expression()?;
+        self.consume(TokenType::Semicolon, "Expected ';'")?;
+        self.builder.finish_node();
+        Ok(())
+    }
+
+    fn expression_statement(&mut self) -> Result<(), SyntaxError> {
+        self.builder.start_node(NodeKind::ExprStmt);
+        self.expression()?;
+        self.consume(TokenType::Semicolon, "Expected ';'")?;
+        self.builder.finish_node();
+        Ok(())
+    }
 
-        while self.match_one_of(vec![TokenType::Plus, TokenType::Minus]) {
-            let operator: Token = self.previous().clone();
-            let right = Box::new(self.factor()?);
+    // every expression rule adds exactly one node,
+    // rules with a left operand wrap it with start_node_at once they see the operator
 
-            let binop_maybe = Parser::op_token_to_binop(&operator);
+    pub fn expression(&mut self) -> Result<(), SyntaxError> {
+        self.nested(Parser::assignment)
+    }
 
-            match binop_maybe {
-                Ok(binop) => {
-                    let left = Box::new(expr);
-                    expr = Expr::Binary(left, binop, right);
-                }
-                Err(e) => return Err(e),
+    pub fn assignment(&mut self) -> Result<(), SyntaxError> {
+        let checkpoint = self.builder.checkpoint();
+        self.ternary()?;
+
+        if self.check(TokenType::Equal) {
+            let target = self.builder.last_node_kind();
+            self.builder.start_node_at(checkpoint, NodeKind::Assign);
+            let equals = self.advance().clone();
+            self.expression()?;
+            self.builder.finish_node();
+
+            if !matches!(
+                target,
+                Some(NodeKind::Variable | NodeKind::Get | NodeKind::Index)
+            ) {
+                return Err(SyntaxError::InvalidAssignment {
+                    line: equals.line,
+                    column: equals.column,
+                });
             }
         }
-        Ok(expr)
+        Ok(())
     }
 
-    pub fn factor(&mut self) -> Result<Expr, SyntaxError> {
-        let mut expr: Expr = self.unary()?;
-
-        while self.match_one_of(vec![TokenType::Star, TokenType::Slash]) {
-            let operator: Token = self.previous().clone();
-            let right = Box::new(self.unary()?);
+    // right associative, a ? b : c ? d : e is a ? b : (c ? d : e)
+    pub fn ternary(&mut self) -> Result<(), SyntaxError> {
+        let checkpoint = self.builder.checkpoint();
+        self.or()?;
 
-            let binop_maybe = Parser::op_token_to_binop(&operator);
-
-            match binop_maybe {
-                Ok(binop) => {
-                    let left = Box::new(expr);
-                    expr = Expr::Binary(left, binop, right);
-                }
-                Err(e) => return Err(e),
+        if self.check(TokenType::QuestionMark) {
+            self.builder.start_node_at(checkpoint, NodeKind::Ternary);
+            self.advance();
+            let location = self.previous_location();
+            self.expression()?;
+            if !self.matches(TokenType::Colon) {
+                return Err(SyntaxError::MissingTernaryColon {
+                    found: Box::new(self.peek().clone()),
+                    line: location.line,
+                    column: location.col,
+                });
             }
+            self.nested(Parser::ternary)?;
+            self.builder.finish_node();
         }
-        Ok(expr)
+        Ok(())
     }
 
-    pub fn unary(&mut self) -> Result<Expr, SyntaxError> {
-        while self.match_one_of(vec![TokenType::Minus, TokenType::Bang]) {
-            let operator: Token = self.previous().clone();
-            let right = Box::new(self.unary()?); // might change to not allow -- or !!
+    pub fn or(&mut self) -> Result<(), SyntaxError> {
+        self.left_associative(NodeKind::Logical, &[TokenType::Or], Parser::and)
+    }
 
-            let uniop_maybe = Parser::op_token_to_uniop(&operator);
+    pub fn and(&mut self) -> Result<(), SyntaxError> {
+        self.left_associative(NodeKind::Logical, &[TokenType::And], Parser::equality)
+    }
 
-            match uniop_maybe {
-                Ok(uniop) => {
-                    let expr = Expr::Unary(uniop, right);
-                    return Ok(expr);
-                }
-                Err(e) => return Err(e),
-            }
-        }
-        self.primary()
+    pub fn equality(&mut self) -> Result<(), SyntaxError> {
+        self.left_associative(
+            NodeKind::Binary,
+            &[TokenType::EqualEqual, TokenType::BangEqual],
+            Parser::comparison,
+        )
     }
 
-    pub fn primary(&mut self) -> Result<Expr, SyntaxError> {
-        if self.matches(TokenType::False) {
-            return Ok(Expr::Literal(Literal::False));
-        }
-        if self.matches(TokenType::True) {
-            return Ok(Expr::Literal(Literal::True));
+    pub fn comparison(&mut self) -> Result<(), SyntaxError> {
+        self.left_associative(
+            NodeKind::Binary,
+            &[
+                TokenType::Less,
+                TokenType::LessEqual,
+                TokenType::Greater,
+                TokenType::GreaterEqual,
+            ],
+            Parser::term,
+        )
+    }
+
+    pub fn term(&mut self) -> Result<(), SyntaxError> {
+        self.left_associative(
+            NodeKind::Binary,
+            &[TokenType::Plus, TokenType::Minus],
+            Parser::factor,
+        )
+    }
+
+    pub fn factor(&mut self) -> Result<(), SyntaxError> {
+        self.left_associative(
+            NodeKind::Binary,
+            &[TokenType::Star, TokenType::Slash],
+            Parser::unary,
+        )
+    }
+
+    // operand ( operator operand )*, each operator wraps everything before it,
+    // so a - b - c is (a - b) - c
+    fn left_associative(
+        &mut self,
+        kind: NodeKind,
+        operators: &[TokenType],
+        operand: fn(&mut Parser) -> Result<(), SyntaxError>,
+    ) -> Result<(), SyntaxError> {
+        let checkpoint = self.builder.checkpoint();
+        operand(self)?;
+
+        while operators.iter().any(|t| self.check(*t)) {
+            self.builder.start_node_at(checkpoint, kind);
+            self.advance();
+            operand(self)?;
+            self.builder.finish_node();
         }
-        if self.matches(TokenType::Null) {
-            return Ok(Expr::Literal(Literal::Null));
+        Ok(())
+    }
+
+    pub fn unary(&mut self) -> Result<(), SyntaxError> {
+        if self.check(TokenType::Minus) || self.check(TokenType::Bang) {
+            self.builder.start_node(NodeKind::Unary);
+            self.advance();
+            self.nested(Parser::unary)?; // might change to not allow -- or !!
+            self.builder.finish_node();
+            return Ok(());
         }
-        if self.matches(TokenType::Number) {
-            match &self.previous().literal {
-                Some(token::Literal::Num(n)) => return Ok(Expr::Literal(Literal::Number(*n))),
-                Some(l) => panic!(
-                    "internal error in parser: when parsing number, found literal {:?}",
-                    l
-                ),
-                None => panic!("internal error in parser: when parsing number, found no literal"),
+        self.call()
+    }
+
+    pub fn call(&mut self) -> Result<(), SyntaxError> {
+        let checkpoint = self.builder.checkpoint();
+        self.primary()?;
+
+        loop {
+            if self.check(TokenType::LeftParen) {
+                self.builder.start_node_at(checkpoint, NodeKind::Call);
+                self.advance();
+                if !self.check(TokenType::RightParen) {
+                    loop {
+                        self.expression()?;
+                        if !self.matches(TokenType::Comma) {
+                            break;
+                        }
+                    }
+                }
+                self.consume(TokenType::RightParen, "Expected ')' after arguments")?;
+            } else if self.check(TokenType::LeftBracket) {
+                self.builder.start_node_at(checkpoint, NodeKind::Index);
+                self.advance();
+                self.expression()?;
+                self.consume(TokenType::RightBracket, "Expected ']' after index")?;
+            } else if self.check(TokenType::Dot) {
+                self.builder.start_node_at(checkpoint, NodeKind::Get);
+                self.advance();
+                self.consume(TokenType::Identifier, "Expected property name after '.'")?;
+            } else {
+                break;
             }
+            self.builder.finish_node();
         }
-        if self.matches(TokenType::String) {
-            match &self.previous().literal {
-                Some(token::Literal::Str(s)) => {
-                    return Ok(Expr::Literal(Literal::String(s.clone())))
-                }
-                Some(l) => panic!(
-                    "parser internal error: when parsing string, found literal {:?}",
-                    l
-                ),
-                None => panic!("parser internal error: when parsing string, found no literal"),
-            }
-        }
-        if self.matches(TokenType::Identifier) {
-            match &self.previous().literal {
-                Some(token::Literal::Identifier(s)) => {
-                    return Ok(Expr::Variable(Symbol {
-                        name: s.clone(),
-                        line: self.previous().line,
-                        column: self.previous().column,
-                    }))
+        Ok(())
+    }
+
+    pub fn primary(&mut self) -> Result<(), SyntaxError> {
+        let kind = match self.peek().token_type {
+            TokenType::False
+            | TokenType::True
+            | TokenType::Null
+            | TokenType::Number
+            | TokenType::String => NodeKind::Literal,
+            TokenType::This => NodeKind::This,
+            TokenType::Identifier => NodeKind::Variable,
+            TokenType::LeftParen => NodeKind::Grouping,
+            TokenType::LeftBracket => NodeKind::List,
+            // statement() has already taken the '{' of a block, so here it starts a map
+            TokenType::LeftCurly => NodeKind::Map,
+            TokenType::Error => {
+                return Err(SyntaxError::UnexpectedToken(Box::new(self.peek().clone())))
+            }
+            _ => {
+                let after = self.is_at_end() && self.current > 0;
+                return Err(SyntaxError::ExpectedExpression {
+                    found: Box::new(self.peek().clone()),
+                    after: after.then(|| Box::new(self.previous().clone())),
+                });
+            }
+        };
+        self.builder.start_node(kind);
+        self.advance();
+
+        match kind {
+            NodeKind::This if self.class_depth == 0 => {
+                let location = self.previous_location();
+                self.errors.push(SyntaxError::OutsideClass {
+                    line: location.line,
+                    column: location.col,
+                });
+            }
+            NodeKind::Grouping => {
+                self.expression()?;
+                self.consume(TokenType::RightParen, "Expected ')' after expression")?;
+            }
+            NodeKind::List => {
+                if !self.check(TokenType::RightBracket) {
+                    loop {
+                        self.expression()?;
+                        if !self.matches(TokenType::Comma) {
+                            break;
+                        }
+                    }
                 }
-                Some(l) => panic!(
-                    "parser internal error: when parsing identifier, found literal {:?}",
-                    l
-                ),
-                None => {
-                    panic!("parser internal error: when parsing identifier, found no literal")
+                self.consume(TokenType::RightBracket, "Expected ']' after list elements")?;
+            }
+            NodeKind::Map => {
+                if !self.check(TokenType::RightCurly) {
+                    loop {
+                        self.builder.start_node(NodeKind::MapEntry);
+                        self.expression()?;
+                        self.consume(TokenType::Colon, "Expected ':' after map key")?;
+                        self.expression()?;
+                        self.builder.finish_node();
+                        if !self.matches(TokenType::Comma) {
+                            break;
+                        }
+                    }
                 }
+                self.consume(TokenType::RightCurly, "Expected '}' after map entries")?;
             }
+            _ => (),
         }
-        if self.matches(TokenType::LeftParen) {
-            let expr = self.expression()?;
-            self.consume(TokenType::RightParen, "Expected ')' after expression")?;
-            return Ok(Expr::Grouping(Box::new(expr)));
-        }
-        Err(SyntaxError::ExpectedExpression {
-            token_type: self.peek().token_type,
-            line: self.peek().line,
-            column: self.peek().column,
-        })
+        self.builder.finish_node();
+        Ok(())
     }
 
     // might not be necessary
@@ -538,120 +1194,78 @@ impl Parser {
     // }
 
     // helper functions
-    #[allow(unused)]
+
+    // runs a rule that can nest, past MAX_NESTING the parser gives up on the rest of
+    // the file instead of running out of stack, here or in what walks the tree later
+    fn nested(
+        &mut self,
+        rule: impl FnOnce(&mut Parser) -> Result<(), SyntaxError>,
+    ) -> Result<(), SyntaxError> {
+        if self.nesting >= MAX_NESTING {
+            let err = SyntaxError::TooDeep {
+                line: self.peek().line,
+                column: self.peek().column,
+            };
+            self.too_deep = true;
+            while !self.is_at_end() {
+                self.advance();
+            }
+            return Err(err);
+        }
+        self.nesting += 1;
+        let result = rule(self);
+        self.nesting -= 1;
+        result
+    }
+
+    // discards tokens until the start of the next statement,
+    // so one syntax error doesn't cause a cascade of others
     fn synchronize(&mut self) {
-        self.advance();
+        // a '{' starts a block, so it's left for the next declaration
+        if self.check(TokenType::LeftCurly) {
+            return;
+        }
+        // a '}' ends the enclosing block, so it's left for the block to consume
+        if !(self.check(TokenType::RightCurly) && self.block_depth > 0) {
+            self.advance();
+        }
 
         while !self.is_at_end() {
             if self.previous().token_type == TokenType::Semicolon {
                 return;
             }
 
+            // a '{' after these is a block, anywhere else it could be a map
+            if self.check(TokenType::LeftCurly)
+                && matches!(
+                    self.previous().token_type,
+                    TokenType::RightParen | TokenType::Else | TokenType::Try
+                )
+            {
+                return;
+            }
+
             match self.peek().token_type {
-                TokenType::Class => (),
-                TokenType::Func => (),
-                TokenType::Var => (),
-                TokenType::If => (),
-                TokenType::For => (),
-                TokenType::While => (),
-                TokenType::Print => (),
-                TokenType::Return => return,
-                _ => todo!(),
+                TokenType::Class
+                | TokenType::Func
+                | TokenType::Var
+                | TokenType::If
+                | TokenType::For
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Break
+                | TokenType::Continue
+                | TokenType::Return
+                | TokenType::Throw
+                | TokenType::Try => return,
+                TokenType::RightCurly if self.block_depth > 0 => return,
+                _ => (),
             }
 
             self.advance();
         }
     }
 
-    fn op_token_to_binop(op: &Token) -> Result<exprstmt::BinaryOp, SyntaxError> {
-        match op.token_type {
-            TokenType::EqualEqual => Ok(exprstmt::BinaryOp {
-                b_type: exprstmt::BinOpType::EqualEqual,
-                line: op.line,
-                column: op.column,
-            }),
-            TokenType::BangEqual => Ok(exprstmt::BinaryOp {
-                b_type: exprstmt::BinOpType::NotEqual,
-                line: op.line,
-                column: op.column,
-            }),
-            TokenType::Less => Ok(exprstmt::BinaryOp {
-                b_type: exprstmt::BinOpType::Less,
-                line: op.line,
-                column: op.column,
-            }),
-            TokenType::LessEqual => Ok(exprstmt::BinaryOp {
-                b_type: exprstmt::BinOpType::LessEqual,
-                line: op.line,
-                column: op.column,
-            }),
-            TokenType::Greater => Ok(exprstmt::BinaryOp {
-                b_type: exprstmt::BinOpType::Greater,
-                line: op.line,
-                column: op.column,
-            }),
-            TokenType::GreaterEqual => Ok(exprstmt::BinaryOp {
-                b_type: exprstmt::BinOpType::GreaterEqual,
-                line: op.line,
-                column: op.column,
-            }),
-            TokenType::Plus => Ok(exprstmt::BinaryOp {
-                b_type: exprstmt::BinOpType::Add,
-                line: op.line,
-                column: op.column,
-            }),
-            TokenType::Minus => Ok(exprstmt::BinaryOp {
-                b_type: exprstmt::BinOpType::Sub,
-                line: op.line,
-                column: op.column,
-            }),
-            TokenType::Star => Ok(exprstmt::BinaryOp {
-                b_type: exprstmt::BinOpType::Mult,
-                line: op.line,
-                column: op.column,
-            }),
-            TokenType::Slash => Ok(exprstmt::BinaryOp {
-                b_type: exprstmt::BinOpType::Div,
-                line: op.line,
-                column: op.column,
-            }),
-            _ => Err(SyntaxError::InvalidTokenInBinaryOp {
-                token_type: op.token_type,
-                line: op.line,
-                column: op.column,
-            }),
-        }
-    }
-
-    fn op_token_to_uniop(op: &Token) -> Result<exprstmt::UnaryOp, SyntaxError> {
-        match op.token_type {
-            TokenType::Bang => Ok(exprstmt::UnaryOp {
-                u_type: exprstmt::UniOpType::Bang,
-                line: op.line,
-                column: op.column,
-            }),
-            TokenType::Minus => Ok(exprstmt::UnaryOp {
-                u_type: exprstmt::UniOpType::Minus,
-                line: op.line,
-                column: op.column,
-            }),
-            _ => Err(SyntaxError::InvalidTokenInUnaryOp {
-                token_type: op.token_type,
-                line: op.line,
-                column: op.column,
-            }),
-        }
-    }
-
-    fn match_one_of(&mut self, types: Vec<TokenType>) -> bool {
-        for t in types.iter() {
-            if self.matches(*t) {
-                return true;
-            }
-        }
-        false
-    }
-
     fn matches(&mut self, t: TokenType) -> bool {
         if self.check(t) {
             self.advance();
@@ -660,8 +1274,10 @@ impl Parser {
         false
     }
 
+    // every consumed token goes in the tree, in the node that is open
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
+            self.builder.token(self.peek().clone());
             self.current += 1
         }
 
@@ -672,10 +1288,14 @@ impl Parser {
         if self.check(t) {
             return Ok(self.advance());
         }
+        // a missing ';' or anything missing at the end of the file is shown right after
+        // the token before it, not on whatever comes next, maybe lines later
+        let after = (t == TokenType::Semicolon || self.is_at_end()) && self.current > 0;
         Err(SyntaxError::TokenMismatch {
             expected: t,
-            found: self.peek().clone(),
+            found: Box::new(self.peek().clone()),
             maybe_err: Some(message.into()),
+            after: after.then(|| Box::new(self.previous().clone())),
         })
     }
 
@@ -699,4 +1319,112 @@ impl Parser {
     fn previous(&self) -> &Token {
         &self.tokens[self.current - 1]
     }
+
+    fn previous_location(&self) -> exprstmt::SourceLocation {
+        exprstmt::SourceLocation {
+            line: self.previous().line,
+            col: self.previous().column,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MAX_NESTING;
+    use crate::interpreter::STACK_SIZE;
+    use crate::scanner;
+    use std::thread;
+
+    fn errors(source: &str) -> Vec<String> {
+        let (tokens, _) = scanner::scan(source.to_string());
+        let (_, errors) = super::parse_cst(tokens);
+        errors.iter().map(|e| e.to_diagnostic().message).collect()
+    }
+
+    //only the first mistake is reported, the block after it parses like it would without it
+    #[test]
+    fn recovers_at_blocks() {
+        assert_eq!(
+            errors("func f( { return 1; }\nprint f;"),
+            ["Expected parameter name"]
+        );
+        assert_eq!(
+            errors("class A { m(a b) { return 1; } }"),
+            ["Expected ')' after parameters"]
+        );
+        assert_eq!(
+            errors("if (x { print 1; }\nprint 2;"),
+            ["Expected ')' after if condition"]
+        );
+        assert_eq!(
+            errors("func f() { while (x + ) { return 1; } }"),
+            ["Expected expression"]
+        );
+        assert_eq!(
+            errors("var m = 1 2 {\"a\": 1};\nprint m;"),
+            ["Expected ';' after variable declaration."]
+        );
+    }
+    //a missing ';' is shown right after what it should follow, labelled with what came instead
+    #[test]
+    fn anchors_missing_semicolons() {
+        let spans = |source: &str| {
+            let (tokens, _) = scanner::scan(source.to_string());
+            let (_, errors) = super::parse_cst(tokens);
+            errors
+                .iter()
+                .map(|e| {
+                    let diagnostic = e.to_diagnostic();
+                    let span = diagnostic.span;
+                    (span.line, span.column, diagnostic.labels[0].message.clone())
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            spans("print 3\n"),
+            [(1, 8, "found end of file".to_string())]
+        );
+        assert_eq!(
+            spans("print 1 +\n\n"),
+            [(1, 10, "found end of file".to_string())]
+        );
+        assert_eq!(
+            spans("var s = \"a\nbc\"\nprint s;"),
+            [(2, 4, "found 'print'".to_string())]
+        );
+        assert_eq!(spans("var = 2;"), [(1, 5, "found '='".to_string())]);
+    }
+
+    //nesting past the limit is a single error instead of a stack overflow
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth: usize| {
+            [
+                format!("print {}1{};", "(".repeat(depth), ")".repeat(depth)),
+                format!("print {}1{};", "[".repeat(depth), "]".repeat(depth)),
+                format!("print {}1;", "-".repeat(depth)),
+                format!("{}print 1;{}", "{".repeat(depth), "}".repeat(depth)),
+                format!("{}print 1;", "if (true) ".repeat(depth)),
+            ]
+        };
+        //main gives the parser this much stack, a test thread has far less
+        let checks = move || {
+            for source in nested(MAX_NESTING - 1) {
+                assert!(errors(&source).is_empty(), "{source}");
+            }
+            for source in nested(3000) {
+                assert_eq!(
+                    errors(&source),
+                    ["Expressions and blocks are nested too deeply"],
+                    "{source}"
+                );
+            }
+        };
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(checks)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
 }
@@ -0,0 +1,174 @@
+use std::io::{self, BufRead, Write};
+
+use crate::interpreter::Interpreter;
+use crate::parser;
+use crate::parser::exprstmt::{Expr, Stmt};
+use crate::resolver;
+use crate::scanner::token::{Token, TokenType};
+use crate::scanner::{self, ErrorKind};
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+//shown in place of a file name in diagnostics
+const REPL_NAME: &str = "<repl>";
+
+//reads lines from stdin and runs them against one interpreter until EOF
+pub fn run() -> io::Result<()> {
+    let mut interpreter = Interpreter::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut buffer = String::new();
+
+    loop {
+        print!(
+            "{}",
+            if buffer.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            }
+        );
+        io::stdout().flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        buffer.push_str(&line);
+        buffer.push('\n');
+
+        if is_complete(&buffer) {
+            eval(&mut interpreter, &buffer);
+            buffer.clear();
+        }
+    }
+    println!();
+    Ok(())
+}
+
+//false if the input is unfinished and more lines are needed, an open string or bracket,
+//input with other scan errors is complete so they are reported right away
+fn is_complete(source: &str) -> bool {
+    let (tokens, errors) = scanner::scan(source.to_string());
+    if errors
+        .iter()
+        .any(|e| e.kind == ErrorKind::UnterminatedString)
+    {
+        return false;
+    }
+    !errors.is_empty() || !is_unbalanced(&tokens)
+}
+
+//scans, parses and runs the complete input
+fn eval(interpreter: &mut Interpreter, source: &str) {
+    let (mut tokens, errors) = scanner::scan(source.to_string());
+    if !errors.is_empty() {
+        for e in &errors {
+            eprint!("{}", e.to_diagnostic().render(source, REPL_NAME));
+        }
+        return;
+    }
+    add_missing_semicolon(&mut tokens);
+
+    let (stmts, errors) = parser::parse(tokens);
+    if !errors.is_empty() {
+        for err in &errors {
+            eprint!("{}", err.to_diagnostic().render(source, REPL_NAME));
+        }
+        return;
+    }
+
+    let errors = resolver::resolve(&stmts);
+    if !errors.is_empty() {
+        for err in &errors {
+            eprint!("{}", err.to_diagnostic().render(source, REPL_NAME));
+        }
+        return;
+    }
+
+    for stmt in &stmts {
+        let result = match stmt {
+            //bare expressions echo their value, assignments stay quiet
+            Stmt::Expression(expr)
+                if !matches!(
+                    expr,
+                    Expr::Assignment(..) | Expr::Set(..) | Expr::SetIndex(..)
+                ) =>
+            {
+                interpreter.evaluate(expr).map(|val| println!("{val}"))
+            }
+            _ => interpreter.interpret(std::slice::from_ref(stmt)),
+        };
+        if let Err(e) = result {
+            eprint!("{}", e.to_diagnostic().render(source, REPL_NAME));
+            break;
+        }
+    }
+}
+
+//true if there are more opening than closing brackets
+fn is_unbalanced(tokens: &[Token]) -> bool {
+    let mut depth: i64 = 0;
+    for token in tokens {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftCurly => depth += 1,
+            TokenType::RightParen | TokenType::RightBracket | TokenType::RightCurly => depth -= 1,
+            _ => (),
+        }
+    }
+    depth > 0
+}
+
+//lets a line like `1 + 2` be typed without the trailing ';'
+fn add_missing_semicolon(tokens: &mut Vec<Token>) {
+    let eof = tokens.len() - 1;
+    if eof == 0 {
+        return;
+    }
+    let last = &tokens[eof - 1];
+    if last.token_type == TokenType::Semicolon || last.token_type == TokenType::RightCurly {
+        return;
+    }
+    let semicolon = Token {
+        token_type: TokenType::Semicolon,
+        lexeme: b";".to_vec(),
+        literal: None,
+        line: last.line,
+        column: last.column + 1,
+        offset: last.offset + last.lexeme.len(),
+        leading: Box::default(),
+    };
+    tokens.insert(eof, semicolon);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_complete;
+
+    #[test]
+    fn open_brackets_continue() {
+        assert!(is_complete("print 1;\n"));
+        assert!(!is_complete("func f() {\n"));
+        assert!(!is_complete("func f() {\n  return [1,\n"));
+        assert!(is_complete("func f() {\n  return [1,\n  2];\n}\n"));
+        assert!(!is_complete("print (1 +\n"));
+        //too many closing brackets can't be fixed by more lines, the parser reports them
+        assert!(is_complete("print 1);\n"));
+        //brackets in strings and comments don't count
+        assert!(is_complete("print \"(\"; // {\n"));
+    }
+
+    #[test]
+    fn open_strings_continue() {
+        assert!(!is_complete("print \"one\n"));
+        assert!(is_complete("print \"one\ntwo\";\n"));
+        assert!(!is_complete("print \"a\\\"\n"));
+    }
+
+    //other scan errors can't be fixed by more lines either
+    #[test]
+    fn scan_errors_are_complete() {
+        assert!(is_complete("print @;\n"));
+        assert!(is_complete("{ print #\n"));
+    }
+}
@@ -0,0 +1,517 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use crate::diagnostic::{Diagnostic, Span};
+use crate::parser::exprstmt::{Expr, FunctionDecl, Slot, Stmt, Symbol};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    ReadInOwnInitializer,
+    AlreadyDeclared,
+}
+
+//static errors found after parsing, the program isn't run if there are any
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Span,
+    //the declaration the error refers back to
+    pub declared: Span,
+}
+
+impl Error {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        match self.kind {
+            ErrorKind::ReadInOwnInitializer => {
+                Diagnostic::error("E0301", self.message.clone(), self.span)
+                    .with_label(self.declared, "declared here")
+                    .with_note("the variable has no value until its initializer has finished")
+            }
+            ErrorKind::AlreadyDeclared => {
+                Diagnostic::error("E0302", self.message.clone(), self.span)
+                    .with_label(self.declared, "first declared here")
+                    .with_note("use a different name, or assign to the existing variable")
+            }
+        }
+    }
+}
+
+//a variable that is read or assigned and the local it resolved to,
+//None for globals since they are looked up by name when the program runs
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub span: Span,
+    pub declared: Option<Span>,
+}
+
+//works out which scope and slot every local variable refers to, storing it in the tree,
+//the global scope isn't tracked so top-level variables can be redefined in the repl
+pub fn resolve(stmts: &[Stmt]) -> Vec<Error> {
+    resolve_references(stmts).0
+}
+
+//resolves like resolve and also returns every variable reference, for editor tools
+pub fn resolve_references(stmts: &[Stmt]) -> (Vec<Error>, Vec<Reference>) {
+    let mut resolver = Resolver {
+        scopes: vec![],
+        errors: vec![],
+        references: vec![],
+    };
+    resolver.resolve_stmts(stmts);
+    (resolver.errors, resolver.references)
+}
+
+struct Local {
+    index: usize,
+    //false while the initializer is being resolved
+    defined: bool,
+    span: Span,
+}
+
+#[derive(Default)]
+struct Scope {
+    names: HashMap<String, Local>,
+    //the number of slots, the interpreter adds one per declaration in the same order
+    len: usize,
+}
+
+struct Resolver {
+    scopes: Vec<Scope>,
+    errors: Vec<Error>,
+    references: Vec<Reference>,
+}
+
+impl Resolver {
+    fn resolve_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(e) | Stmt::Print(e) | Stmt::Throw(_, e) => self.resolve_expr(e),
+            Stmt::VarDeclaration(sym, init) => {
+                self.declare(sym);
+                if let Some(init) = init {
+                    self.resolve_expr(init);
+                }
+                self.define(sym);
+            }
+            Stmt::Block(stmts) => {
+                self.scopes.push(Scope::default());
+                self.resolve_stmts(stmts);
+                self.scopes.pop();
+            }
+            Stmt::If(_, cond, then_branch, else_branch) => {
+                self.resolve_expr(cond);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            Stmt::While(_, cond, body) => {
+                self.resolve_expr(cond);
+                self.resolve_stmt(body);
+            }
+            //the loop variable gets its own scope around the loop, like in the interpreter
+            Stmt::For(_, init, cond, increment, body) => {
+                self.scopes.push(Scope::default());
+                if let Some(init) = init {
+                    self.resolve_stmt(init);
+                }
+                if let Some(cond) = cond {
+                    self.resolve_expr(cond);
+                }
+                if let Some(increment) = increment {
+                    self.resolve_expr(increment);
+                }
+                self.resolve_stmt(body);
+                self.scopes.pop();
+            }
+            Stmt::Break | Stmt::Continue => (),
+            //the name is defined first so the function can call itself
+            Stmt::Function(decl) => {
+                self.declare(&decl.name);
+                self.define(&decl.name);
+                self.resolve_function(decl);
+            }
+            Stmt::Return(e) => {
+                if let Some(e) = e {
+                    self.resolve_expr(e);
+                }
+            }
+            //methods are bound in a scope that only holds 'this'
+            Stmt::Class(decl) => {
+                self.declare(&decl.name);
+                self.define(&decl.name);
+                for method in &decl.methods {
+                    self.scopes.push(Scope::default());
+                    let this = Symbol {
+                        name: "this".to_string(),
+                        line: method.name.line,
+                        column: method.name.column,
+                    };
+                    self.declare(&this);
+                    self.define(&this);
+                    self.resolve_function(method);
+                    self.scopes.pop();
+                }
+            }
+            //the caught error shares a scope with the handler's statements
+            Stmt::Try(body, name, handler) => {
+                self.scopes.push(Scope::default());
+                self.resolve_stmts(body);
+                self.scopes.pop();
+
+                self.scopes.push(Scope::default());
+                self.declare(name);
+                self.define(name);
+                self.resolve_stmts(handler);
+                self.scopes.pop();
+            }
+        }
+    }
+
+    //the parameters share a scope with the body's statements
+    fn resolve_function(&mut self, decl: &FunctionDecl) {
+        self.scopes.push(Scope::default());
+        for param in &decl.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_stmts(&decl.body);
+        self.scopes.pop();
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(_) => (),
+            Expr::This(_, slot) => {
+                self.resolve_local("this", slot);
+            }
+            Expr::Variable(sym, slot) => {
+                if let Some(local) = self.scopes.last().and_then(|s| s.names.get(&sym.name)) {
+                    if !local.defined {
+                        self.errors.push(Error {
+                            kind: ErrorKind::ReadInOwnInitializer,
+                            message: format!(
+                                "Variable {} is read in its own initializer",
+                                sym.name
+                            ),
+                            span: sym.span(),
+                            declared: local.span,
+                        });
+                    }
+                }
+                self.resolve_reference(sym, slot);
+            }
+            Expr::Assignment(sym, value, slot) => {
+                self.resolve_expr(value);
+                self.resolve_reference(sym, slot);
+            }
+            Expr::Unary(_, e) | Expr::Grouping(e) | Expr::Get(e, _) => self.resolve_expr(e),
+            Expr::Binary(left, _, right)
+            | Expr::Logical(left, _, right)
+            | Expr::Set(left, _, right)
+            | Expr::Index(left, _, right) => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Ternary(cond, _, then_branch, else_branch) => {
+                self.resolve_expr(cond);
+                self.resolve_expr(then_branch);
+                self.resolve_expr(else_branch);
+            }
+            Expr::Call(callee, _, args) => {
+                self.resolve_expr(callee);
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            Expr::List(elements) => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            Expr::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            Expr::SetIndex(object, _, index, value) => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+                self.resolve_expr(value);
+            }
+        }
+    }
+
+    //adds the name to the innermost scope, global declarations aren't tracked
+    fn declare(&mut self, sym: &Symbol) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        if let Some(previous) = scope.names.get(&sym.name) {
+            self.errors.push(Error {
+                kind: ErrorKind::AlreadyDeclared,
+                message: format!("{} is already declared in this scope", sym.name),
+                span: sym.span(),
+                declared: previous.span,
+            });
+        }
+        //a duplicate still takes a slot, keeping the numbering in step with the interpreter
+        let local = Local {
+            index: scope.len,
+            defined: false,
+            span: sym.span(),
+        };
+        scope.names.insert(sym.name.clone(), local);
+        scope.len += 1;
+    }
+
+    fn define(&mut self, sym: &Symbol) {
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|s| s.names.get_mut(&sym.name))
+        {
+            local.defined = true;
+        }
+    }
+
+    fn resolve_reference(&mut self, sym: &Symbol, slot: &Cell<Option<Slot>>) {
+        let declared = self.resolve_local(&sym.name, slot);
+        self.references.push(Reference {
+            name: sym.name.clone(),
+            span: sym.span(),
+            declared,
+        });
+    }
+
+    //a name that isn't in any scope is left unresolved, making it a global,
+    //returns where the local was declared
+    fn resolve_local(&mut self, name: &str, slot: &Cell<Option<Slot>>) -> Option<Span> {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(local) = scope.names.get(name) {
+                slot.set(Some(Slot {
+                    depth,
+                    index: local.index,
+                }));
+                return Some(local.span);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve, ErrorKind};
+    use crate::diagnostic::Span;
+    use crate::parser::exprstmt::{Expr, Stmt};
+    use crate::{parser, scanner};
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let (tokens, _) = scanner::scan(source.to_string());
+        let (stmts, errors) = parser::parse(tokens);
+        assert!(errors.is_empty(), "{source}");
+        stmts
+    }
+
+    //the kind, span and declaration span of every error
+    fn errors(source: &str) -> Vec<(ErrorKind, Span, Span)> {
+        resolve(&parse(source))
+            .into_iter()
+            .map(|e| (e.kind, e.span, e.declared))
+            .collect()
+    }
+
+    //every variable, assignment and this with the depth and index it resolved to, in order
+    fn slots(source: &str) -> Vec<(String, Option<(usize, usize)>)> {
+        let stmts = parse(source);
+        resolve(&stmts);
+        let mut slots = Vec::new();
+        for stmt in &stmts {
+            stmt_slots(stmt, &mut slots);
+        }
+        slots
+    }
+
+    //only walks what the tests below use
+    fn stmt_slots(stmt: &Stmt, slots: &mut Vec<(String, Option<(usize, usize)>)>) {
+        match stmt {
+            Stmt::Expression(e) | Stmt::Print(e) | Stmt::Throw(_, e) | Stmt::Return(Some(e)) => {
+                expr_slots(e, slots)
+            }
+            Stmt::VarDeclaration(_, Some(e)) => expr_slots(e, slots),
+            Stmt::Block(stmts) => stmts.iter().for_each(|s| stmt_slots(s, slots)),
+            Stmt::Function(decl) => decl.body.iter().for_each(|s| stmt_slots(s, slots)),
+            Stmt::Class(decl) => {
+                for method in &decl.methods {
+                    method.body.iter().for_each(|s| stmt_slots(s, slots));
+                }
+            }
+            Stmt::Try(body, _, handler) => {
+                body.iter().for_each(|s| stmt_slots(s, slots));
+                handler.iter().for_each(|s| stmt_slots(s, slots));
+            }
+            _ => (),
+        }
+    }
+
+    fn expr_slots(expr: &Expr, slots: &mut Vec<(String, Option<(usize, usize)>)>) {
+        let (name, slot) = match expr {
+            Expr::Variable(sym, slot) => (sym.name.clone(), slot),
+            Expr::Assignment(sym, value, slot) => {
+                expr_slots(value, slots);
+                (sym.name.clone(), slot)
+            }
+            Expr::This(_, slot) => ("this".to_string(), slot),
+            Expr::Get(object, _) => return expr_slots(object, slots),
+            Expr::Binary(left, _, right) => {
+                expr_slots(left, slots);
+                return expr_slots(right, slots);
+            }
+            Expr::Call(callee, _, args) => {
+                expr_slots(callee, slots);
+                return args.iter().for_each(|a| expr_slots(a, slots));
+            }
+            _ => return,
+        };
+        let slot = slot.get().map(|slot| (slot.depth, slot.index));
+        slots.push((name, slot));
+    }
+
+    fn slot(name: &str, depth: usize, index: usize) -> (String, Option<(usize, usize)>) {
+        (name.to_string(), Some((depth, index)))
+    }
+
+    fn global(name: &str) -> (String, Option<(usize, usize)>) {
+        (name.to_string(), None)
+    }
+
+    #[test]
+    fn read_in_own_initializer() {
+        assert_eq!(
+            errors("{\n  var a = 1;\n  {\n    var a = a + 1;\n  }\n}"),
+            [(
+                ErrorKind::ReadInOwnInitializer,
+                Span::new(4, 13, 1),
+                Span::new(4, 9, 1)
+            )]
+        );
+        //globals are looked up when the program runs, so this reads the old a
+        assert_eq!(errors("var a = 1;\nvar a = a + 1;"), []);
+    }
+
+    #[test]
+    fn already_declared() {
+        assert_eq!(
+            errors("{ var a; var a; }"),
+            [(
+                ErrorKind::AlreadyDeclared,
+                Span::new(1, 14, 1),
+                Span::new(1, 7, 1)
+            )]
+        );
+        assert_eq!(
+            errors("func f(a, a) {}"),
+            [(
+                ErrorKind::AlreadyDeclared,
+                Span::new(1, 11, 1),
+                Span::new(1, 8, 1)
+            )]
+        );
+        //a parameter and a variable in the body share a scope
+        assert_eq!(
+            errors("func f(a) { var a; }"),
+            [(
+                ErrorKind::AlreadyDeclared,
+                Span::new(1, 17, 1),
+                Span::new(1, 8, 1)
+            )]
+        );
+        //the global scope isn't tracked and an inner scope may shadow
+        assert_eq!(errors("var a; var a; { var a; { var a; } }"), []);
+    }
+
+    //slots are numbered in declaration order, the duplicate still takes one
+    #[test]
+    fn assigns_slots_in_order() {
+        assert_eq!(
+            slots("{ var a = 1; var b = 2; print b; a = b; }"),
+            [slot("b", 0, 1), slot("b", 0, 1), slot("a", 0, 0)]
+        );
+        assert_eq!(
+            slots("var g; { var a; var a; var b; print b + g; }"),
+            [slot("b", 0, 2), global("g")]
+        );
+    }
+
+    #[test]
+    fn shadowing() {
+        assert_eq!(
+            slots("{ var a = 1; { var b; var a = 2; print a; } print a; }"),
+            [slot("a", 0, 1), slot("a", 0, 0)]
+        );
+        assert_eq!(
+            slots("var g; { var a; { var b; print a + b + g; } }"),
+            [slot("a", 1, 0), slot("b", 0, 0), global("g")]
+        );
+    }
+
+    //a function's parameters and locals are one scope, the enclosing ones are further out
+    #[test]
+    fn closures_capture_outer_slots() {
+        assert_eq!(
+            slots(
+                "func outer(p) {
+                    var x = 1;
+                    func inner(q) {
+                        return p + x + q + inner(q);
+                    }
+                    return inner;
+                }"
+            ),
+            [
+                slot("p", 1, 0),
+                slot("x", 1, 1),
+                slot("q", 0, 0),
+                slot("inner", 1, 2),
+                slot("q", 0, 0),
+                slot("inner", 0, 2),
+            ]
+        );
+    }
+
+    //this lives in a scope of its own around the method's scope
+    #[test]
+    fn this_in_methods() {
+        assert_eq!(
+            slots("class A { m(a) { return this.x + a; } n() { { return this; } } }"),
+            [slot("this", 1, 0), slot("a", 0, 0), slot("this", 2, 0)]
+        );
+    }
+
+    //the caught error is the first slot of the handler, the try body has its own scope
+    #[test]
+    fn catch_variable_scope() {
+        assert_eq!(
+            slots("try { var a = 1; throw a; } catch (e) { var b = e; print b; } print e;"),
+            [
+                slot("a", 0, 0),
+                slot("e", 0, 0),
+                slot("b", 0, 1),
+                global("e")
+            ]
+        );
+        assert_eq!(
+            slots("{ var x; try { throw x; } catch (e) { print e + x; } }"),
+            [slot("x", 1, 0), slot("e", 0, 0), slot("x", 1, 0)]
+        );
+    }
+}
@@ -1,7 +1,10 @@
-use crate::scanner::token::{Literal, Token, TokenType};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::scanner::token::{Literal, Token, TokenType, Trivia, TriviaKind};
 
 use std::collections::HashMap;
 
+use unicode_xid::UnicodeXID;
+
 pub mod token {
     use std::fmt;
 
@@ -39,9 +42,15 @@ pub mod token {
         String,
         Number,
 
+        // Text the scanner couldn't make sense of, reported as an Error.
+        Error,
+
         // Keywords.
         And,
+        Break,
+        Catch,
         Class,
+        Continue,
         Else,
         False,
         Func,
@@ -52,7 +61,9 @@ pub mod token {
         Print,
         Return,
         This,
+        Throw,
         True,
+        Try,
         Var,
         While,
 
@@ -66,6 +77,23 @@ pub mod token {
         Num(f64),
     }
 
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum TriviaKind {
+        // A run of spaces, tabs and carriage returns.
+        Whitespace,
+        Newline,
+        Comment,
+    }
+
+    //source text the parser doesn't need, kept for tools like the formatter
+    #[derive(Debug, Clone)]
+    pub struct Trivia {
+        pub kind: TriviaKind,
+        pub text: String,
+        pub line: usize,
+        pub column: i64,
+    }
+
     #[derive(Clone)]
     pub struct Token {
         pub token_type: TokenType,
@@ -73,6 +101,10 @@ pub mod token {
         pub literal: Option<Literal>,
         pub line: usize,
         pub column: i64,
+        //the byte offset of the lexeme in the source
+        pub offset: usize,
+        //the trivia between the previous token and this one, Eof gets what ends the file
+        pub leading: Box<[Trivia]>,
     }
 
     impl fmt::Debug for Token {
@@ -90,51 +122,83 @@ pub mod token {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+    InvalidCharacter,
+    UnterminatedString,
+    InvalidEscape,
+}
+
 #[derive(Debug)]
 //static errors
 pub struct Error {
-    //error handling is done in main if the error value in the scanner is Some and not None
+    //error handling is done in main if the scanner returns any errors
+    pub kind: ErrorKind,
     pub message: String,
     pub line: usize,
     pub column: i64,
+    pub len: usize,
 }
 
-//the function that main calls which creates the scanner
-pub fn scan(input: String) -> Result<Vec<Token>, Error> {
+impl Error {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let span = Span::new(self.line, self.column, self.len);
+        match self.kind {
+            ErrorKind::InvalidCharacter => Diagnostic::error("E0001", self.message.clone(), span),
+            ErrorKind::UnterminatedString => Diagnostic::error("E0002", self.message.clone(), span)
+                .with_label(span, "string starts here")
+                .with_note("add a '\"' to close the string"),
+            ErrorKind::InvalidEscape => Diagnostic::error("E0003", self.message.clone(), span)
+                .with_note("valid escapes are \\n, \\t, \\r, \\\\, \\\", \\0 and \\u{...}"),
+        }
+    }
+}
+
+//the function that main calls which creates the scanner,
+//the tokens are returned even when there are errors, with an Error token in place of bad input
+pub fn scan(input: String) -> (Vec<Token>, Vec<Error>) {
     let mut scanner: Scanner = Default::default();
     scanner.scan_tokens(input);
 
-    match scanner.err {
-        Some(err) => Err(err),
-        None => Ok(scanner.tokens),
-    }
+    (scanner.tokens, scanner.errors)
 }
 
 pub struct Scanner {
-    source: Vec<u8>,
+    source: String,
     tokens: Vec<Token>,
-    err: Option<Error>,
+    errors: Vec<Error>,
+    //trivia waiting for the next token
+    trivia: Vec<Trivia>,
     start: usize,
     current: usize,
     line: usize,
     column: i64,
+    //position of the first character of the current lexeme
+    start_line: usize,
+    start_column: i64,
     keywords: HashMap<String, TokenType>,
 }
 
 impl Default for Scanner {
     fn default() -> Scanner {
         Scanner {
-            source: Vec::new(),
+            source: String::new(),
             tokens: Vec::new(),
-            err: None,
+            errors: Vec::new(),
+            trivia: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
-            column: -1,
+            column: 0,
+            start_line: 1,
+            start_column: 1,
             //create a hashmap from a list of tuples(saves a lot of boilerplate String::from)
             keywords: vec![
                 ("and", TokenType::And),
+                ("break", TokenType::Break),
+                ("catch", TokenType::Catch),
                 ("class", TokenType::Class),
+                ("continue", TokenType::Continue),
                 ("else", TokenType::Else),
                 ("false", TokenType::False),
                 ("for", TokenType::For),
@@ -145,7 +209,9 @@ impl Default for Scanner {
                 ("print", TokenType::Print),
                 ("return", TokenType::Return),
                 ("this", TokenType::This),
+                ("throw", TokenType::Throw),
                 ("true", TokenType::True),
+                ("try", TokenType::Try),
                 ("var", TokenType::Var),
                 ("while", TokenType::While),
             ]
@@ -159,12 +225,14 @@ impl Default for Scanner {
 impl Scanner {
     //create a vec of all the tokens from an input
     pub fn scan_tokens(&mut self, input: String) -> Vec<Token> {
-        self.source = input.into_bytes();
+        self.source = input;
 
         let tokens: Vec<Token> = Vec::new();
         while !self.is_at_end() {
             // beginning of the lexeme
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column + 1;
             self.scan_token();
         }
 
@@ -174,7 +242,9 @@ impl Scanner {
             lexeme: Vec::new(),
             literal: None,
             line: self.line,
-            column: self.column,
+            column: self.column + 1,
+            offset: self.current,
+            leading: std::mem::take(&mut self.trivia).into_boxed_slice(),
         });
         tokens
     }
@@ -235,16 +305,23 @@ impl Scanner {
             '/' => {
                 let matches = self.matches('/');
                 if matches {
-                    while self.peek() != '\n' {
+                    while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    self.add_trivia(TriviaKind::Comment);
                 } else {
                     self.add_token(TokenType::Slash);
                 }
             }
-            //ingore white space
-            ' ' | '\r' | '\t' => {}
+            //white space is kept as trivia
+            ' ' | '\r' | '\t' => {
+                while matches!(self.peek(), ' ' | '\r' | '\t') {
+                    self.advance();
+                }
+                self.add_trivia(TriviaKind::Whitespace);
+            }
             '\n' => {
+                self.add_trivia(TriviaKind::Newline);
                 self.line += 1;
                 self.column = 0;
             }
@@ -254,14 +331,13 @@ impl Scanner {
             _ => {
                 if c.is_ascii_digit() {
                     self.number();
-                } else if c.is_ascii_alphabetic() || c == '_' {
+                } else if c.is_xid_start() || c == '_' {
                     self.identifier();
                 } else {
-                    self.err = Some(Error {
-                        message: format!("Invalid character found: {c}"),
-                        line: self.line,
-                        column: self.column,
-                    })
+                    self.error(
+                        ErrorKind::InvalidCharacter,
+                        format!("Invalid character found: {c}"),
+                    );
                 }
             }
         }
@@ -271,51 +347,47 @@ impl Scanner {
     //character in the list and consumes it if true
     fn matches(&mut self, c: char) -> bool {
         if self.is_at_end() {
-            return true;
+            return false;
         }
-        if char::from(self.source[self.current]) != c {
+        if self.peek() != c {
             return false;
         }
-        self.current += 1;
-        self.column += 1;
+        self.advance();
         true
     }
 
     //gets the next character in the list
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            char::from(self.source[self.current])
-        }
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     //gets the character after the next character in the list
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            '\0'
-        } else {
-            char::from(self.source[self.current + 1])
-        }
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
     //handles strings
     fn string(&mut self) {
+        //the value with escape sequences decoded
+        let mut value = String::new();
+
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\\' {
-                todo!(); //escape characters
-            }
-            if self.peek() == '\n' {
-                self.line += 1;
+            match self.advance() {
+                '\\' => self.escape(&mut value),
+                '\n' => {
+                    self.line += 1;
+                    self.column = 0;
+                    value.push('\n');
+                }
+                c => value.push(c),
             }
-            self.advance();
         }
         if self.is_at_end() {
-            self.err = Some(Error {
-                message: "String needs to be closed".to_string(),
-                line: self.line,
-                column: self.column,
-            })
+            self.error(
+                ErrorKind::UnterminatedString,
+                "String needs to be closed".to_string(),
+            );
+            return;
         }
 
         //eliminated all other options
@@ -323,13 +395,74 @@ impl Scanner {
 
         self.advance();
 
-        self.add_token_literal(
-            TokenType::String,
-            Some(Literal::Str(
-                //creates a string from bytes without the quotes
-                String::from_utf8(self.source[self.start + 1..self.current - 1].to_vec()).unwrap(),
-            )),
-        );
+        self.add_token_literal(TokenType::String, Some(Literal::Str(value)));
+    }
+
+    //decodes the escape sequence after a backslash, invalid ones are reported and left out
+    fn escape(&mut self, value: &mut String) {
+        let (line, column) = (self.line, self.column);
+        if self.is_at_end() {
+            //reported as an unterminated string
+            return;
+        }
+
+        let decoded = match self.advance() {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '\\' => '\\',
+            '"' => '"',
+            '0' => '\0',
+            'u' => match self.unicode_escape() {
+                Ok(c) => c,
+                Err(message) => {
+                    let len = (self.column - column + 1) as usize;
+                    self.error_at(ErrorKind::InvalidEscape, message, line, column, len);
+                    return;
+                }
+            },
+            c => {
+                if c == '\n' {
+                    self.line += 1;
+                    self.column = 0;
+                }
+                self.error_at(
+                    ErrorKind::InvalidEscape,
+                    format!("Unknown escape sequence: \\{}", c.escape_default()),
+                    line,
+                    column,
+                    2,
+                );
+                return;
+            }
+        };
+
+        value.push(decoded);
+    }
+
+    //handles the {...} part of a \u{...} escape, up to 6 hex digits
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        if self.peek() != '{' {
+            return Err("Expected '{' after \\u".to_string());
+        }
+        self.advance();
+
+        let digits_start = self.current;
+        while self.peek().is_ascii_hexdigit() {
+            self.advance();
+        }
+        let digits = self.source[digits_start..self.current].to_string();
+
+        if self.peek() != '}' {
+            return Err("Expected '}' to close the \\u{...} escape".to_string());
+        }
+        self.advance();
+
+        if digits.is_empty() || digits.len() > 6 {
+            return Err("A \\u{...} escape needs 1 to 6 hex digits".to_string());
+        }
+        let code = u32::from_str_radix(&digits, 16).unwrap();
+        char::from_u32(code).ok_or(format!("{digits} is not a valid unicode character"))
     }
 
     //handles numbers
@@ -347,20 +480,17 @@ impl Scanner {
             }
         }
 
-        let val: f64 = String::from_utf8(self.source[self.start..self.current].to_vec())
-            .unwrap()
-            .parse()
-            .unwrap();
+        let val: f64 = self.source[self.start..self.current].parse().unwrap();
         self.add_token_literal(TokenType::Number, Some(Literal::Num(val)))
     }
 
     //handles keywords and identifiers
     fn identifier(&mut self) {
-        while self.peek().is_ascii_alphanumeric() {
+        while self.peek().is_xid_continue() {
             self.advance();
         }
 
-        let val = String::from_utf8(self.source[self.start..self.current].to_vec()).unwrap();
+        let val = self.source[self.start..self.current].to_string();
 
         let token_type = match self.keywords.get(&val) {
             Some(kw_token_type) => *kw_token_type,
@@ -376,11 +506,30 @@ impl Scanner {
     }
 
     //move one character forward in the input
+    //positions are byte offsets into the source, columns count characters
     fn advance(&mut self) -> char {
-        self.current += 1;
+        let c = self.peek();
+        self.current += c.len_utf8();
         self.column += 1;
 
-        char::from(self.source[self.current - 1])
+        c
+    }
+
+    //records an error at the start of the current lexeme and adds an Error token for it
+    fn error(&mut self, kind: ErrorKind, message: String) {
+        self.error_at(kind, message, self.start_line, self.start_column, 1);
+        self.add_token(TokenType::Error);
+    }
+
+    //records an error inside a token, which is still added
+    fn error_at(&mut self, kind: ErrorKind, message: String, line: usize, column: i64, len: usize) {
+        self.errors.push(Error {
+            kind,
+            message,
+            line,
+            column,
+            len,
+        });
     }
 
     //buffer function for non-literal tokens
@@ -390,14 +539,26 @@ impl Scanner {
 
     //create and adds a token to the vec
     fn add_token_literal(&mut self, token_type: TokenType, literal: Option<Literal>) {
-        let text = self.source[self.start..self.current].to_vec();
+        let text = self.source.as_bytes()[self.start..self.current].to_vec();
 
         self.tokens.push(Token {
             token_type,
             lexeme: text,
             literal,
-            line: self.line,
-            column: self.column,
+            line: self.start_line,
+            column: self.start_column,
+            offset: self.start,
+            leading: std::mem::take(&mut self.trivia).into_boxed_slice(),
+        })
+    }
+
+    //records the current lexeme as trivia for the next token
+    fn add_trivia(&mut self, kind: TriviaKind) {
+        self.trivia.push(Trivia {
+            kind,
+            text: self.source[self.start..self.current].to_string(),
+            line: self.start_line,
+            column: self.start_column,
         })
     }
 
@@ -406,3 +567,219 @@ impl Scanner {
         self.current >= self.source.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::token::{Literal, TokenType};
+    use super::{scan, ErrorKind};
+
+    //the type, text, line and column of every token
+    fn tokens(source: &str) -> Vec<(TokenType, String, usize, i64)> {
+        let (tokens, _) = scan(source.to_string());
+        tokens
+            .iter()
+            .map(|t| {
+                let lexeme = String::from_utf8(t.lexeme.clone()).unwrap();
+                (t.token_type, lexeme, t.line, t.column)
+            })
+            .collect()
+    }
+
+    //the decoded value of the string the source starts with, and the messages of its errors
+    fn string(source: &str) -> (String, Vec<String>) {
+        let (tokens, errors) = scan(source.to_string());
+        let value = match &tokens[0].literal {
+            Some(Literal::Str(value)) => value.clone(),
+            literal => panic!("not a string: {literal:?}"),
+        };
+        (value, errors.into_iter().map(|e| e.message).collect())
+    }
+
+    //the kind, line, column and length of every error
+    fn errors(source: &str) -> Vec<(ErrorKind, usize, i64, usize)> {
+        let (_, errors) = scan(source.to_string());
+        errors
+            .iter()
+            .map(|e| (e.kind, e.line, e.column, e.len))
+            .collect()
+    }
+
+    //scanning goes on after an error, so every one of them is reported at once
+    #[test]
+    fn reports_every_error() {
+        assert_eq!(
+            errors("var a = 1 @ 2;\nprint # $;\n\"open"),
+            [
+                (ErrorKind::InvalidCharacter, 1, 11, 1),
+                (ErrorKind::InvalidCharacter, 2, 7, 1),
+                (ErrorKind::InvalidCharacter, 2, 9, 1),
+                (ErrorKind::UnterminatedString, 3, 1, 1),
+            ]
+        );
+    }
+
+    //bad input becomes an Error token, the parser reports it instead of skipping over it
+    #[test]
+    fn keeps_error_tokens() {
+        let types: Vec<_> = tokens("a @ b \"open")
+            .into_iter()
+            .map(|(token_type, lexeme, _, _)| (token_type, lexeme))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                (TokenType::Identifier, "a".to_string()),
+                (TokenType::Error, "@".to_string()),
+                (TokenType::Identifier, "b".to_string()),
+                (TokenType::Error, "\"open".to_string()),
+                (TokenType::Eof, String::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn positions_after_an_error() {
+        let positions: Vec<_> = tokens("@ x\n # y;")
+            .into_iter()
+            .map(|(_, lexeme, line, column)| (lexeme, line, column))
+            .collect();
+        assert_eq!(
+            positions,
+            [
+                ("@".to_string(), 1, 1),
+                ("x".to_string(), 1, 3),
+                ("#".to_string(), 2, 2),
+                ("y".to_string(), 2, 4),
+                (";".to_string(), 2, 5),
+                (String::new(), 2, 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(
+            string(r#""a\nb\tc\\d\"e\r\0""#),
+            ("a\nb\tc\\d\"e\r\0".to_string(), vec![])
+        );
+        assert_eq!(
+            string(r#""\u{41}\u{e9}\u{1F600}""#),
+            ("Aé😀".to_string(), vec![])
+        );
+    }
+
+    //a bad escape is reported and left out, the rest of the string is kept
+    #[test]
+    fn reports_bad_escapes() {
+        assert_eq!(
+            string(r#""a\qb""#),
+            (
+                "ab".to_string(),
+                vec![r"Unknown escape sequence: \q".to_string()]
+            )
+        );
+        for (source, message) in [
+            (r#""\u41""#, r"Expected '{' after \u"),
+            (r#""\u{41""#, r"Expected '}' to close the \u{...} escape"),
+            (r#""\u{4g}""#, r"Expected '}' to close the \u{...} escape"),
+            (r#""\u{}""#, r"A \u{...} escape needs 1 to 6 hex digits"),
+            (
+                r#""\u{1000000}""#,
+                r"A \u{...} escape needs 1 to 6 hex digits",
+            ),
+            (r#""\u{D800}""#, "D800 is not a valid unicode character"),
+            (r#""\u{110000}""#, "110000 is not a valid unicode character"),
+        ] {
+            assert_eq!(string(source).1, [message], "{source}");
+        }
+        //the span covers the backslash up to where the escape went wrong
+        assert_eq!(
+            errors(r#"x = "ok\u{}";"#),
+            [(ErrorKind::InvalidEscape, 1, 8, 4)]
+        );
+    }
+
+    #[test]
+    fn positions_in_multi_line_strings() {
+        assert_eq!(
+            errors("\"one\ntwo \\q\nthree\" @"),
+            [
+                (ErrorKind::InvalidEscape, 2, 5, 2),
+                (ErrorKind::InvalidCharacter, 3, 8, 1),
+            ]
+        );
+        //the string is where it starts, what follows counts columns from the last line
+        let positions: Vec<_> = tokens("\"a\nbc\" d\ne")
+            .into_iter()
+            .map(|(token_type, _, line, column)| (token_type, line, column))
+            .collect();
+        assert_eq!(
+            positions,
+            [
+                (TokenType::String, 1, 1),
+                (TokenType::Identifier, 2, 5),
+                (TokenType::Identifier, 3, 1),
+                (TokenType::Eof, 3, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        //the second character of each only continues an identifier, it can't start one
+        for name in ["café", "_x1", "数据", "λ·μ", "e\u{301}", "π2"] {
+            let source = format!("{name};");
+            assert_eq!(
+                tokens(&source)[0],
+                (TokenType::Identifier, name.to_string(), 1, 1),
+                "{name}"
+            );
+            assert_eq!(errors(&source), [], "{name}");
+        }
+    }
+
+    #[test]
+    fn rejects_characters_outside_identifiers() {
+        for c in ["😀", "\u{301}", "·", "#", "€"] {
+            let source = format!("a {c} b");
+            let types: Vec<_> = tokens(&source).into_iter().map(|t| t.0).collect();
+            assert_eq!(
+                types,
+                [
+                    TokenType::Identifier,
+                    TokenType::Error,
+                    TokenType::Identifier,
+                    TokenType::Eof
+                ],
+                "{c}"
+            );
+            assert_eq!(
+                errors(&source),
+                [(ErrorKind::InvalidCharacter, 1, 3, 1)],
+                "{c}"
+            );
+        }
+    }
+
+    //columns count characters, 𝑥 is four bytes and 日本 six but they are one and two columns
+    #[test]
+    fn columns_count_characters() {
+        let columns: Vec<_> = tokens("var 𝑥 = \"é\"; 日本 x")
+            .into_iter()
+            .map(|(_, lexeme, _, column)| (lexeme, column))
+            .collect();
+        assert_eq!(
+            columns,
+            [
+                ("var".to_string(), 1),
+                ("𝑥".to_string(), 5),
+                ("=".to_string(), 7),
+                ("\"é\"".to_string(), 9),
+                (";".to_string(), 12),
+                ("日本".to_string(), 14),
+                ("x".to_string(), 17),
+                (String::new(), 18),
+            ]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::interpreter::environment::{MapKey, Value};
+use crate::interpreter::native::{NativeFn, Registry};
+
+//defines the standard library functions in the global scope of either backend
+pub fn register(runtime: &mut impl Registry) {
+    //seconds since the unix epoch, for timing programs
+    runtime.define_native(NativeFn::new("clock", 0, |_| {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| e.to_string())?;
+        Ok(now.as_secs_f64().into())
+    }));
+
+    //the number of characters in a string, elements in a list or keys in a map
+    runtime.define_native(NativeFn::new("len", 1, |args| match first(args) {
+        Value::String(s) => Ok((s.chars().count() as f64).into()),
+        Value::List(list) => Ok((list.borrow().len() as f64).into()),
+        Value::Map(map) => Ok((map.borrow().len() as f64).into()),
+        val => Err(format!(
+            "expected a string, a list or a map, found {}",
+            val.type_name()
+        )),
+    }));
+
+    //adds a value to the end of a list
+    runtime.define_native(NativeFn::new("push", 2, |args| {
+        let mut args = args.into_iter();
+        match (args.next(), args.next()) {
+            (Some(Value::List(list)), Some(val)) => {
+                list.borrow_mut().push(val);
+                Ok(Value::Null)
+            }
+            (Some(val), _) => Err(format!("expected a list, found {}", val.type_name())),
+            _ => Ok(Value::Null),
+        }
+    }));
+
+    //prints the prompt and reads a line, null at the end of the input
+    runtime.define_native(NativeFn::new("input", 1, |args| {
+        let prompt: String = first(args).try_into()?;
+        print!("{prompt}");
+        io::stdout().flush().map_err(|e| e.to_string())?;
+
+        let mut line = String::new();
+        let read = io::stdin()
+            .lock()
+            .read_line(&mut line)
+            .map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Ok(Value::Null);
+        }
+        Ok(line.trim_end_matches(['\n', '\r']).into())
+    }));
+
+    //the keys of a map as a list, in the order they were added
+    runtime.define_native(NativeFn::new("keys", 1, |args| match first(args) {
+        Value::Map(map) => {
+            let keys = map.borrow().keys().iter().map(MapKey::to_value).collect();
+            Ok(Value::List(Rc::new(RefCell::new(keys))))
+        }
+        val => Err(format!("expected a map, found {}", val.type_name())),
+    }));
+
+    //true if the map has the key
+    runtime.define_native(NativeFn::new("has", 2, |args| {
+        let mut args = args.into_iter();
+        match (args.next(), args.next()) {
+            (Some(Value::Map(map)), Some(key)) => Ok(MapKey::from_value(&key)
+                .is_some_and(|key| map.borrow().get(&key).is_some())
+                .into()),
+            (Some(val), _) => Err(format!("expected a map, found {}", val.type_name())),
+            _ => Ok(Value::Null),
+        }
+    }));
+
+    //any value as it would be printed
+    runtime.define_native(NativeFn::new("str", 1, |args| {
+        Ok(first(args).to_string().into())
+    }));
+
+    //parses a string into a number
+    runtime.define_native(NativeFn::new("num", 1, |args| {
+        let s: String = first(args).try_into()?;
+        match s.trim().parse::<f64>() {
+            Ok(n) => Ok(n.into()),
+            Err(_) => Err(format!("\"{s}\" is not a number")),
+        }
+    }));
+}
+
+//the arity is checked before the call, so one-argument functions always have it
+fn first(args: Vec<Value>) -> Value {
+    args.into_iter().next().unwrap_or(Value::Null)
+}
@@ -0,0 +1,398 @@
+use std::fmt;
+
+//a json value, objects keep their keys in the order they were added so the output is stable
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object<const N: usize>(entries: [(&str, Json); N]) -> Json {
+        Json::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    //the error says what was wrong and at which byte
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            text: text.as_bytes(),
+            current: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.current < parser.text.len() {
+            return Err(parser.error("unexpected text after the value"));
+        }
+        Ok(value)
+    }
+
+    //the value of a key in an object, None for missing keys and other values
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Json {
+        Json::Number(n)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Json {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(option: Option<T>) -> Json {
+        option.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(items: Vec<T>) -> Json {
+        Json::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+//compact, on one line
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            //json has no infinity or nan
+            Json::Number(n) if !n.is_finite() => write!(f, "null"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+// -------
+// Parsing
+// -------
+
+struct Parser<'a> {
+    text: &'a [u8],
+    current: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'[') => self.array(),
+            Some(b'{') => self.object(),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if self.text[self.current..].starts_with(word.as_bytes()) {
+            self.current += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.current;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.peek() {
+            self.current += 1;
+        }
+        //the bytes are all ascii, so this can't fail
+        let text = std::str::from_utf8(&self.text[start..self.current]).unwrap();
+        text.parse()
+            .map(Json::Number)
+            .map_err(|_| format!("invalid number '{text}' at byte {start}"))
+    }
+
+    //after the opening quote up to and including the closing one
+    fn string(&mut self) -> Result<String, String> {
+        self.current += 1;
+        let mut bytes = vec![];
+        loop {
+            match self.advance() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    let c = match self.advance() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape in string")),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                Some(b) => bytes.push(b),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.error("invalid utf-8 in string"))
+    }
+
+    //after the \u, characters outside the basic plane are written as two escapes
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if self.advance() != Some(b'\\') || self.advance() != Some(b'u') {
+                return Err(self.error("unpaired surrogate in string"));
+            }
+            let low = self.hex4()?;
+            0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.current..self.current + 4)
+            .and_then(|d| std::str::from_utf8(d).ok())
+            .and_then(|d| u32::from_str_radix(d, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.current += 4;
+        Ok(digits)
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.current += 1;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.current += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.advance() {
+                Some(b',') => (),
+                Some(b']') => return Ok(Json::Array(items)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.current += 1;
+        let mut entries = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.current += 1;
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key in object"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            if self.advance() != Some(b':') {
+                return Err(self.error("expected ':' after key"));
+            }
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.advance() {
+                Some(b',') => (),
+                Some(b'}') => return Ok(Json::Object(entries)),
+                _ => return Err(self.error("expected ',' or '}' in object")),
+            }
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.current += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.current).copied()
+    }
+
+    //stays at the end, so errors there are at the length of the text
+    fn advance(&mut self) -> Option<u8> {
+        let b = self.peek();
+        if b.is_some() {
+            self.current += 1;
+        }
+        b
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("{message} at byte {}", self.current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Json;
+
+    #[test]
+    fn escapes_strings() {
+        //only control characters below 0x20 need escapes, everything else is written as is
+        let value = Json::from("say \"hi\"\\ \n\r\t\u{1}\u{1f}\u{7f} é 😀");
+        let text = value.to_string();
+        assert_eq!(
+            text,
+            "\"say \\\"hi\\\"\\\\ \\n\\r\\t\\u0001\\u001f\u{7f} é 😀\""
+        );
+        assert_eq!(Json::parse(&text), Ok(value));
+    }
+
+    //keys stay in the order they were added, and there is no whitespace
+    #[test]
+    fn keeps_field_order() {
+        let value = Json::object([
+            ("z", Json::Null),
+            ("a", vec![1.0, 2.5].into()),
+            (
+                "m",
+                Json::object([("b", true.into()), ("a", f64::NAN.into())]),
+            ),
+        ]);
+        assert_eq!(
+            value.to_string(),
+            r#"{"z":null,"a":[1,2.5],"m":{"b":true,"a":null}}"#
+        );
+    }
+
+    #[test]
+    fn parses() {
+        let value = Json::parse(r#" { "a" : [1, -2.5e1, "é😀\/"], "b": {} } "#);
+        assert_eq!(
+            value,
+            Ok(Json::object([
+                (
+                    "a",
+                    Json::Array(vec![1.0.into(), (-25.0).into(), "é😀/".into()])
+                ),
+                ("b", Json::object([])),
+            ]))
+        );
+        assert_eq!(
+            Json::parse("[1, 2"),
+            Err("expected ',' or ']' in array at byte 5".to_string())
+        );
+        assert_eq!(
+            Json::parse(r#""\ud83d""#),
+            Err("unpaired surrogate in string at byte 8".to_string())
+        );
+        assert_eq!(
+            Json::parse("1 2"),
+            Err("unexpected text after the value at byte 2".to_string())
+        );
+    }
+}
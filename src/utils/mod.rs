@@ -1,2 +1,3 @@
+pub mod json;
 pub mod print_ast;
 pub mod print_token;
@@ -1,37 +1,437 @@
-use crate::parser::exprstmt::Expr;
+use std::cell::Cell;
 
-pub fn pr(expr: Expr) {
-    println!("{}", format(expr));
+use crate::parser::exprstmt::{Expr, FunctionDecl, Literal, Slot, Stmt};
+
+//prints the program as s-expressions, one line per top-level statement
+pub fn pr(stmts: &[Stmt]) {
+    print!("{}", sexpr(stmts));
+}
+
+//prints the program as an indented tree, with the position of every node that has one
+pub fn pr_tree(stmts: &[Stmt]) {
+    print!("{}", tree(stmts));
+}
+
+pub fn sexpr(stmts: &[Stmt]) -> String {
+    stmts.iter().map(|stmt| format_stmt(stmt) + "\n").collect()
 }
 
-fn format(expr: Expr) -> String {
+pub fn tree(stmts: &[Stmt]) -> String {
+    let mut printer = TreePrinter::default();
+    for stmt in stmts {
+        printer.stmt("", stmt);
+    }
+    printer.out
+}
+
+// -------------
+// S-expressions
+// -------------
+
+fn format_stmt(stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::Expression(e) => format!("(expr {})", format(e)),
+        Stmt::Print(e) => format!("(print {})", format(e)),
+        Stmt::VarDeclaration(sym, None) => format!("(var {})", sym.name),
+        Stmt::VarDeclaration(sym, Some(init)) => format!("(var {} {})", sym.name, format(init)),
+        Stmt::Block(stmts) => list("block", stmts.iter().map(format_stmt)),
+        Stmt::If(_, cond, then_branch, None) => {
+            format!("(if {} {})", format(cond), format_stmt(then_branch))
+        }
+        Stmt::If(_, cond, then_branch, Some(else_branch)) => format!(
+            "(if {} {} {})",
+            format(cond),
+            format_stmt(then_branch),
+            format_stmt(else_branch)
+        ),
+        Stmt::While(_, cond, body) => format!("(while {} {})", format(cond), format_stmt(body)),
+        //a missing part of the header is ()
+        Stmt::For(_, init, cond, increment, body) => format!(
+            "(for {} {} {} {})",
+            init.as_ref().map_or("()".to_string(), |s| format_stmt(s)),
+            cond.as_ref().map_or("()".to_string(), format),
+            increment.as_ref().map_or("()".to_string(), format),
+            format_stmt(body)
+        ),
+        Stmt::Break => "(break)".to_string(),
+        Stmt::Continue => "(continue)".to_string(),
+        Stmt::Function(decl) => format_function(decl),
+        Stmt::Return(None) => "(return)".to_string(),
+        Stmt::Return(Some(e)) => format!("(return {})", format(e)),
+        Stmt::Class(decl) => list(
+            &format!("class {}", decl.name.name),
+            decl.methods.iter().map(|method| format_function(method)),
+        ),
+        Stmt::Throw(_, e) => format!("(throw {})", format(e)),
+        Stmt::Try(body, name, handler) => format!(
+            "(try {} {})",
+            list("block", body.iter().map(format_stmt)),
+            list(
+                &format!("catch {}", name.name),
+                handler.iter().map(format_stmt)
+            )
+        ),
+    }
+}
+
+fn format_function(decl: &FunctionDecl) -> String {
+    let params = decl
+        .params
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>()
+        .join(" ");
+    list(
+        &format!("func {} ({params})", decl.name.name),
+        decl.body.iter().map(format_stmt),
+    )
+}
+
+fn format(expr: &Expr) -> String {
     match expr {
-        Expr::Grouping(expr) => parenthesize("group".to_string(), expr),
-        Expr::Unary(op, expr) => parenthesize(format!("{}", op.u_type), expr),
-        Expr::Binary(left, op, right) => parenthesize_bin(format!("{}", op.b_type), left, right),
-        Expr::Ternary(bool, if_, else_) => parenthesize_tri(bool, if_, else_),
-        Expr::Literal(value) => return format!("{value}"),
-        Expr::Variable(name) => todo!(),
-        Expr::Assignment(sym, expr) => todo!(),
+        Expr::Grouping(expr) => format!("(group {})", format(expr)),
+        Expr::Unary(op, expr) => format!("({} {})", op.u_type, format(expr)),
+        Expr::Binary(left, op, right) => {
+            format!("({} {} {})", op.b_type, format(left), format(right))
+        }
+        Expr::Logical(left, op, right) => {
+            format!("({} {} {})", op.l_type, format(left), format(right))
+        }
+        Expr::Ternary(cond, _, then_branch, else_branch) => format!(
+            "(? {} {} {})",
+            format(cond),
+            format(then_branch),
+            format(else_branch)
+        ),
+        Expr::Literal(lit) => format_literal(lit),
+        Expr::Variable(sym, _) => sym.name.clone(),
+        Expr::This(..) => "this".to_string(),
+        Expr::Get(object, name) => format!("(. {} {})", format(object), name.name),
+        Expr::Set(object, name, value) => {
+            format!("(= (. {} {}) {})", format(object), name.name, format(value))
+        }
+        Expr::Call(callee, _, args) => {
+            list(&format!("call {}", format(callee)), args.iter().map(format))
+        }
+        Expr::List(elements) => list("list", elements.iter().map(format)),
+        Expr::Map(_, entries) => list(
+            "map",
+            entries
+                .iter()
+                .map(|(key, value)| format!("({} {})", format(key), format(value))),
+        ),
+        Expr::Index(object, _, index) => format!("([] {} {})", format(object), format(index)),
+        Expr::SetIndex(object, _, index, value) => format!(
+            "(= ([] {} {}) {})",
+            format(object),
+            format(index),
+            format(value)
+        ),
+        Expr::Assignment(sym, expr, _) => format!("(= {} {})", sym.name, format(expr)),
     }
 }
 
-fn parenthesize_tri(bool: Box<Expr>, if_: Box<Expr>, else_: Box<Expr>) -> String {
-    let s: String = String::from(format!(
-        "({} ? {} : {})",
-        format(*bool),
-        format(*if_),
-        format(*else_)
-    ));
-    s
+//strings are quoted so they can't be mistaken for names
+fn format_literal(lit: &Literal) -> String {
+    match lit {
+        Literal::String(s) => format!("{s:?}"),
+        _ => lit.to_string(),
+    }
 }
 
-fn parenthesize_bin(name: String, left: Box<Expr>, right: Box<Expr>) -> String {
-    let s: String = String::from(format!("({} {} {})", format(*left), name, format(*right)));
-    s
+//(head item item ...)
+fn list(head: &str, items: impl Iterator<Item = String>) -> String {
+    let mut s = format!("({head}");
+    for item in items {
+        s += " ";
+        s += &item;
+    }
+    s + ")"
 }
 
-fn parenthesize(name: String, expr: Box<Expr>) -> String {
-    let s: String = String::from(format!("({} {})", name, format(*expr)));
-    s
+// ----
+// Tree
+// ----
+
+#[derive(Default)]
+struct TreePrinter {
+    out: String,
+    depth: usize,
+}
+
+impl TreePrinter {
+    //writes one node on its own line, the field says which part of the parent it is
+    fn line(&mut self, field: &str, text: &str) {
+        self.out += &"  ".repeat(self.depth);
+        if !field.is_empty() {
+            self.out += field;
+            self.out += ": ";
+        }
+        self.out += text;
+        self.out += "\n";
+    }
+
+    fn children(&mut self, f: impl FnOnce(&mut TreePrinter)) {
+        self.depth += 1;
+        f(self);
+        self.depth -= 1;
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        self.children(|p| {
+            for stmt in stmts {
+                p.stmt("", stmt);
+            }
+        });
+    }
+
+    fn stmt(&mut self, field: &str, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(e) => {
+                self.line(field, "Expression");
+                self.children(|p| p.expr("", e));
+            }
+            Stmt::Print(e) => {
+                self.line(field, "Print");
+                self.children(|p| p.expr("", e));
+            }
+            Stmt::VarDeclaration(sym, init) => {
+                self.line(
+                    field,
+                    &format!("Var {} @{}:{}", sym.name, sym.line, sym.column),
+                );
+                if let Some(init) = init {
+                    self.children(|p| p.expr("init", init));
+                }
+            }
+            Stmt::Block(stmts) => {
+                self.line(field, "Block");
+                self.stmts(stmts);
+            }
+            Stmt::If(loc, cond, then_branch, else_branch) => {
+                self.line(field, &format!("If @{}:{}", loc.line, loc.col));
+                self.children(|p| {
+                    p.expr("cond", cond);
+                    p.stmt("then", then_branch);
+                    if let Some(else_branch) = else_branch {
+                        p.stmt("else", else_branch);
+                    }
+                });
+            }
+            Stmt::While(loc, cond, body) => {
+                self.line(field, &format!("While @{}:{}", loc.line, loc.col));
+                self.children(|p| {
+                    p.expr("cond", cond);
+                    p.stmt("body", body);
+                });
+            }
+            Stmt::For(loc, init, cond, increment, body) => {
+                self.line(field, &format!("For @{}:{}", loc.line, loc.col));
+                self.children(|p| {
+                    if let Some(init) = init {
+                        p.stmt("init", init);
+                    }
+                    if let Some(cond) = cond {
+                        p.expr("cond", cond);
+                    }
+                    if let Some(increment) = increment {
+                        p.expr("increment", increment);
+                    }
+                    p.stmt("body", body);
+                });
+            }
+            Stmt::Break => self.line(field, "Break"),
+            Stmt::Continue => self.line(field, "Continue"),
+            Stmt::Function(decl) => self.function(field, "Function", decl),
+            Stmt::Return(e) => {
+                self.line(field, "Return");
+                if let Some(e) = e {
+                    self.children(|p| p.expr("", e));
+                }
+            }
+            Stmt::Class(decl) => {
+                let name = &decl.name;
+                self.line(
+                    field,
+                    &format!("Class {} @{}:{}", name.name, name.line, name.column),
+                );
+                self.children(|p| {
+                    for method in &decl.methods {
+                        p.function("", "Method", method);
+                    }
+                });
+            }
+            Stmt::Throw(loc, e) => {
+                self.line(field, &format!("Throw @{}:{}", loc.line, loc.col));
+                self.children(|p| p.expr("", e));
+            }
+            Stmt::Try(body, name, handler) => {
+                self.line(field, "Try");
+                self.children(|p| {
+                    p.line("body", "Block");
+                    p.stmts(body);
+                    p.line(
+                        "catch",
+                        &format!("Catch {} @{}:{}", name.name, name.line, name.column),
+                    );
+                    p.stmts(handler);
+                });
+            }
+        }
+    }
+
+    fn function(&mut self, field: &str, kind: &str, decl: &FunctionDecl) {
+        let name = &decl.name;
+        self.line(
+            field,
+            &format!("{kind} {} @{}:{}", name.name, name.line, name.column),
+        );
+        self.children(|p| {
+            for param in &decl.params {
+                p.line(
+                    "param",
+                    &format!("{} @{}:{}", param.name, param.line, param.column),
+                );
+            }
+        });
+        self.stmts(&decl.body);
+    }
+
+    fn expr(&mut self, field: &str, expr: &Expr) {
+        match expr {
+            Expr::Literal(lit) => self.line(field, &format!("Literal {}", format_literal(lit))),
+            Expr::Grouping(e) => {
+                self.line(field, "Grouping");
+                self.children(|p| p.expr("", e));
+            }
+            Expr::Unary(op, e) => {
+                self.line(
+                    field,
+                    &format!("Unary {} @{}:{}", op.u_type, op.line, op.column),
+                );
+                self.children(|p| p.expr("", e));
+            }
+            Expr::Binary(left, op, right) => {
+                self.line(
+                    field,
+                    &format!("Binary {} @{}:{}", op.b_type, op.line, op.column),
+                );
+                self.children(|p| {
+                    p.expr("", left);
+                    p.expr("", right);
+                });
+            }
+            Expr::Logical(left, op, right) => {
+                self.line(
+                    field,
+                    &format!("Logical {} @{}:{}", op.l_type, op.line, op.column),
+                );
+                self.children(|p| {
+                    p.expr("", left);
+                    p.expr("", right);
+                });
+            }
+            Expr::Ternary(cond, loc, then_branch, else_branch) => {
+                self.line(field, &format!("Ternary @{}:{}", loc.line, loc.col));
+                self.children(|p| {
+                    p.expr("cond", cond);
+                    p.expr("then", then_branch);
+                    p.expr("else", else_branch);
+                });
+            }
+            Expr::Variable(sym, slot) => self.line(
+                field,
+                &format!(
+                    "Variable {} @{}:{} {}",
+                    sym.name,
+                    sym.line,
+                    sym.column,
+                    format_slot(slot)
+                ),
+            ),
+            Expr::Assignment(sym, value, slot) => {
+                self.line(
+                    field,
+                    &format!(
+                        "Assign {} @{}:{} {}",
+                        sym.name,
+                        sym.line,
+                        sym.column,
+                        format_slot(slot)
+                    ),
+                );
+                self.children(|p| p.expr("", value));
+            }
+            Expr::This(loc, slot) => self.line(
+                field,
+                &format!("This @{}:{} {}", loc.line, loc.col, format_slot(slot)),
+            ),
+            Expr::Call(callee, loc, args) => {
+                self.line(field, &format!("Call @{}:{}", loc.line, loc.col));
+                self.children(|p| {
+                    p.expr("callee", callee);
+                    for arg in args {
+                        p.expr("arg", arg);
+                    }
+                });
+            }
+            Expr::Get(object, name) => {
+                self.line(
+                    field,
+                    &format!("Get {} @{}:{}", name.name, name.line, name.column),
+                );
+                self.children(|p| p.expr("", object));
+            }
+            Expr::Set(object, name, value) => {
+                self.line(
+                    field,
+                    &format!("Set {} @{}:{}", name.name, name.line, name.column),
+                );
+                self.children(|p| {
+                    p.expr("object", object);
+                    p.expr("value", value);
+                });
+            }
+            Expr::List(elements) => {
+                self.line(field, "List");
+                self.children(|p| {
+                    for element in elements {
+                        p.expr("", element);
+                    }
+                });
+            }
+            Expr::Map(loc, entries) => {
+                self.line(field, &format!("Map @{}:{}", loc.line, loc.col));
+                self.children(|p| {
+                    for (key, value) in entries {
+                        p.expr("key", key);
+                        p.expr("value", value);
+                    }
+                });
+            }
+            Expr::Index(object, loc, index) => {
+                self.line(field, &format!("Index @{}:{}", loc.line, loc.col));
+                self.children(|p| {
+                    p.expr("object", object);
+                    p.expr("index", index);
+                });
+            }
+            Expr::SetIndex(object, loc, index, value) => {
+                self.line(field, &format!("SetIndex @{}:{}", loc.line, loc.col));
+                self.children(|p| {
+                    p.expr("object", object);
+                    p.expr("index", index);
+                    p.expr("value", value);
+                });
+            }
+        }
+    }
+}
+
+//where the resolver put the variable, globals are looked up by name
+fn format_slot(slot: &Cell<Option<Slot>>) -> String {
+    match slot.get() {
+        Some(slot) => format!("(local {}:{})", slot.depth, slot.index),
+        None => "(global)".to_string(),
+    }
 }
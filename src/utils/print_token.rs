@@ -1,7 +1,65 @@
-use crate::scanner::token::Token;
+use crate::scanner::token::{Literal, Token};
+use crate::utils::json::Json;
 
 pub fn pr(tokens: &Vec<Token>) {
     for token in tokens {
         println!("{token:?}");
     }
 }
+
+//prints one json object per token
+pub fn pr_json(tokens: &[Token]) {
+    for token in tokens {
+        println!("{}", json(token));
+    }
+}
+
+//the span is the byte range of the lexeme in the source
+pub fn json(token: &Token) -> Json {
+    let literal = match &token.literal {
+        Some(Literal::Identifier(name)) => Json::from(name.as_str()),
+        Some(Literal::Str(s)) => Json::from(s.as_str()),
+        Some(Literal::Num(n)) => Json::from(*n),
+        None => Json::Null,
+    };
+    Json::object([
+        ("type", format!("{:?}", token.token_type).into()),
+        (
+            "lexeme",
+            String::from_utf8_lossy(&token.lexeme).into_owned().into(),
+        ),
+        ("literal", literal),
+        ("line", token.line.into()),
+        ("column", token.column.into()),
+        (
+            "span",
+            Json::object([
+                ("start", token.offset.into()),
+                ("end", (token.offset + token.lexeme.len()).into()),
+            ]),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scanner;
+
+    //columns count characters, spans are byte offsets into the source
+    #[test]
+    fn json() {
+        let (tokens, _) = scanner::scan("var 𝑥 = \"é\\\"\";".to_string());
+        let lines: Vec<String> = tokens.iter().map(|t| super::json(t).to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                r#"{"type":"Var","lexeme":"var","literal":null,"line":1,"column":1,"span":{"start":0,"end":3}}"#,
+                r#"{"type":"Identifier","lexeme":"𝑥","literal":"𝑥","line":1,"column":5,"span":{"start":4,"end":8}}"#,
+                r#"{"type":"Equal","lexeme":"=","literal":null,"line":1,"column":7,"span":{"start":9,"end":10}}"#,
+                r#"{"type":"String","lexeme":"\"é\\\"\"","literal":"é\"","line":1,"column":9,"span":{"start":11,"end":17}}"#,
+                r#"{"type":"Semicolon","lexeme":";","literal":null,"line":1,"column":14,"span":{"start":17,"end":18}}"#,
+                r#"{"type":"Eof","lexeme":"","literal":null,"line":1,"column":15,"span":{"start":18,"end":18}}"#,
+            ]
+        );
+    }
+}
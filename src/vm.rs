@@ -0,0 +1,747 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::diagnostic::Span;
+use crate::interpreter::environment::{self, Class, Globals, Instance, Map, Value};
+use crate::interpreter::native::{NativeFn, NativeFunction, Registry};
+use crate::interpreter::{ops, RuntimeError, MAX_CALL_DEPTH};
+use crate::parser::exprstmt::{BinOpType, LogicOpType, UniOpType};
+use crate::stdlib;
+use crate::vm::chunk::{Check, Constant, OpCode, Prototype};
+
+//passed to the runtime operations in place of the instruction's span,
+//which is only looked up in the line table once there is an error
+const PENDING_SPAN: Span = Span {
+    line: 0,
+    column: 0,
+    len: 0,
+};
+
+//the compiled form of a program, made by the compiler and run by the vm
+pub mod chunk {
+    use std::rc::Rc;
+
+    use crate::diagnostic::Span;
+
+    //every instruction is one byte, followed by its operands,
+    //u8 for slots and counts, u16 (big endian) for constants and jumps
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[repr(u8)]
+    pub enum OpCode {
+        Constant,
+        Null,
+        // the marker a local declared without a value holds
+        Uninitialized,
+        True,
+        False,
+        Pop,
+        GetLocal,
+        SetLocal,
+        GetUpvalue,
+        SetUpvalue,
+        CloseUpvalue,
+        // errors if the variable just read holds the Uninitialized marker, the operand is its name
+        CheckInitialized,
+        GetGlobal,
+        SetGlobal,
+        DefineGlobal,
+        // a global declared without a value
+        DeclareGlobal,
+        GetProperty,
+        SetProperty,
+        GetIndex,
+        SetIndex,
+        Equal,
+        NotEqual,
+        Greater,
+        GreaterEqual,
+        Less,
+        LessEqual,
+        Add,
+        Subtract,
+        Multiply,
+        Divide,
+        Not,
+        Negate,
+        // checks that the value on top of the stack is a boolean, the operand is a Check
+        CheckBool,
+        Jump,
+        // the conditional jumps leave the condition on the stack
+        JumpIfFalse,
+        JumpIfTrue,
+        Loop,
+        Print,
+        Call,
+        Closure,
+        Return,
+        Class,
+        List,
+        Map,
+        MapEntry,
+        Throw,
+        PushHandler,
+        PopHandler,
+    }
+
+    const OPCODES: [OpCode; 48] = [
+        OpCode::Constant,
+        OpCode::Null,
+        OpCode::Uninitialized,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::CloseUpvalue,
+        OpCode::CheckInitialized,
+        OpCode::GetGlobal,
+        OpCode::SetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::DeclareGlobal,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetIndex,
+        OpCode::SetIndex,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::CheckBool,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::JumpIfTrue,
+        OpCode::Loop,
+        OpCode::Print,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::Return,
+        OpCode::Class,
+        OpCode::List,
+        OpCode::Map,
+        OpCode::MapEntry,
+        OpCode::Throw,
+        OpCode::PushHandler,
+        OpCode::PopHandler,
+    ];
+
+    impl OpCode {
+        pub fn from_byte(byte: u8) -> OpCode {
+            let op = OPCODES[byte as usize];
+            debug_assert_eq!(op as u8, byte);
+            op
+        }
+    }
+
+    // what a CheckBool is checking, which decides the error message
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[repr(u8)]
+    pub enum Check {
+        If,
+        While,
+        For,
+        Ternary,
+        And,
+        Or,
+    }
+
+    impl Check {
+        pub fn from_byte(byte: u8) -> Check {
+            [
+                Check::If,
+                Check::While,
+                Check::For,
+                Check::Ternary,
+                Check::And,
+                Check::Or,
+            ][byte as usize]
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub enum Constant {
+        Number(f64),
+        String(Rc<str>),
+        Function(Rc<Prototype>),
+    }
+
+    #[derive(Debug, Default)]
+    pub struct Chunk {
+        pub code: Vec<u8>,
+        pub constants: Vec<Constant>,
+        // the line table, (offset, span) pairs with an entry only where the span changes,
+        // the span of an instruction is the last entry at or before its offset
+        lines: Vec<(usize, Span)>,
+    }
+
+    impl Chunk {
+        pub fn write(&mut self, byte: u8, span: Span) {
+            if self.lines.last().map(|(_, last)| *last) != Some(span) {
+                self.lines.push((self.code.len(), span));
+            }
+            self.code.push(byte);
+        }
+
+        // numbers and strings that are already in the pool are reused
+        pub fn add_constant(&mut self, constant: Constant) -> usize {
+            let existing = self.constants.iter().position(|c| match (c, &constant) {
+                (Constant::Number(a), Constant::Number(b)) => a.to_bits() == b.to_bits(),
+                (Constant::String(a), Constant::String(b)) => a == b,
+                _ => false,
+            });
+            existing.unwrap_or_else(|| {
+                self.constants.push(constant);
+                self.constants.len() - 1
+            })
+        }
+
+        pub fn span_at(&self, offset: usize) -> Span {
+            let i = self.lines.partition_point(|(start, _)| *start <= offset);
+            self.lines[i.saturating_sub(1)].1
+        }
+    }
+
+    // where a closure gets an upvalue from when it is created
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct UpvalueRef {
+        // true for a local of the enclosing function, false for one of its upvalues
+        pub is_local: bool,
+        pub index: u8,
+    }
+
+    // a compiled function, the script itself is one too
+    #[derive(Debug, Default)]
+    pub struct Prototype {
+        pub name: String,
+        pub arity: usize,
+        pub chunk: Chunk,
+        pub upvalues: Vec<UpvalueRef>,
+        pub is_initializer: bool,
+    }
+}
+
+//a variable captured by a closure, it points into the stack
+//until the variable goes out of scope, then it holds the value itself
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct Closure {
+    pub proto: Rc<Prototype>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+//a method taken from an instance, calling it puts the instance in slot 0
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Rc<RefCell<Instance>>,
+    pub method: Rc<Closure>,
+}
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    //the stack index of slot 0, which holds the function itself or 'this'
+    base: usize,
+}
+
+//an active try statement
+struct Handler {
+    frame: usize,
+    stack_len: usize,
+    //where the catch block starts
+    ip: usize,
+}
+
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    globals: Globals,
+    //the upvalues still pointing into the stack
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    handlers: Vec<Handler>,
+    //what a local declared without a value holds until it is given one,
+    //a native function of the vm's own, so no program can get hold of it
+    unset: Rc<dyn NativeFunction>,
+}
+
+//runs a compiled script on a new vm
+pub fn run(script: Rc<Prototype>) -> Result<(), RuntimeError> {
+    let mut vm = Vm::new();
+    vm.run(script)
+}
+
+impl Registry for Vm {
+    fn define_native(&mut self, func: impl NativeFunction + 'static) {
+        let name = func.name().to_string();
+        self.globals
+            .define(&name, Some(Value::Native(Rc::new(func))));
+    }
+}
+
+impl Vm {
+    //a vm with the standard library already defined
+    pub fn new() -> Vm {
+        let mut vm = Vm {
+            stack: Vec::with_capacity(256),
+            frames: Vec::new(),
+            globals: Globals::default(),
+            open_upvalues: Vec::new(),
+            handlers: Vec::new(),
+            unset: Rc::new(NativeFn::new("unset", 0, |_| Ok(Value::Null))),
+        };
+        stdlib::register(&mut vm);
+        vm
+    }
+
+    pub fn run(&mut self, script: Rc<Prototype>) -> Result<(), RuntimeError> {
+        let closure = Rc::new(Closure {
+            proto: script,
+            upvalues: Vec::new(),
+        });
+        self.stack.push(Value::Closure(closure.clone()));
+        self.frames.push(Frame {
+            closure,
+            ip: 0,
+            base: 0,
+        });
+
+        //a runtime error goes to the innermost try statement, if there is one
+        loop {
+            match self.execute() {
+                Ok(()) => return Ok(()),
+                Err(err) => match self.handlers.pop() {
+                    Some(handler) => {
+                        self.frames.truncate(handler.frame + 1);
+                        self.close_upvalues(handler.stack_len);
+                        self.stack.truncate(handler.stack_len);
+                        self.stack.push(err.to_value());
+                        self.frame_mut().ip = handler.ip;
+                    }
+                    None => return Err(err),
+                },
+            }
+        }
+    }
+
+    //runs instructions until the script returns or there is an error
+    fn execute(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            let start = self.frame().ip;
+            let op = OpCode::from_byte(self.read_byte());
+            match op {
+                OpCode::Constant => {
+                    let val = match self.read_constant() {
+                        Constant::Number(n) => Value::Number(n),
+                        Constant::String(s) => Value::String(s),
+                        Constant::Function(_) => unreachable!("functions are loaded by Closure"),
+                    };
+                    self.stack.push(val);
+                }
+                OpCode::Null => self.stack.push(Value::Null),
+                OpCode::Uninitialized => self.stack.push(Value::Native(self.unset.clone())),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.stack.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().base + self.read_byte() as usize;
+                    self.stack.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().base + self.read_byte() as usize;
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let val = match &*self.frame().closure.upvalues[index].borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(val) => val.clone(),
+                    };
+                    self.stack.push(val);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_byte() as usize;
+                    let val = self.peek(0).clone();
+                    let upvalue = self.frame().closure.upvalues[index].clone();
+                    let mut upvalue = upvalue.borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(slot) => self.stack[*slot] = val,
+                        Upvalue::Closed(closed) => *closed = val,
+                    }
+                }
+                OpCode::CheckInitialized => {
+                    let name = self.read_name();
+                    if let Value::Native(func) = self.peek(0) {
+                        if Rc::ptr_eq(func, &self.unset) {
+                            return Err(environment::uninitialized(&name, self.span_at(start)));
+                        }
+                    }
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_name();
+                    let val = self.locate(self.globals.get(&name, PENDING_SPAN), start)?;
+                    self.stack.push(val);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_name();
+                    let val = self.peek(0).clone();
+                    let result = self.globals.assign(&name, &val, PENDING_SPAN);
+                    self.locate(result, start)?;
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_name();
+                    let val = self.pop();
+                    self.globals.define(&name, Some(val));
+                }
+                OpCode::DeclareGlobal => {
+                    let name = self.read_name();
+                    self.globals.define(&name, None);
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_name();
+                    let object = self.pop();
+                    let val =
+                        self.locate(ops::get_property(&object, &name, PENDING_SPAN), start)?;
+                    self.stack.push(val);
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_name();
+                    let val = self.pop();
+                    let object = self.pop();
+                    let result = ops::set_property(&object, &name, val.clone(), PENDING_SPAN);
+                    self.locate(result, start)?;
+                    self.stack.push(val);
+                }
+                OpCode::GetIndex => {
+                    let index = self.pop();
+                    let object = self.pop();
+                    let val = self.locate(ops::get_index(&object, &index, PENDING_SPAN), start)?;
+                    self.stack.push(val);
+                }
+                OpCode::SetIndex => {
+                    let val = self.pop();
+                    let index = self.pop();
+                    let object = self.pop();
+                    let result = ops::set_index(&object, &index, val.clone(), PENDING_SPAN);
+                    self.locate(result, start)?;
+                    self.stack.push(val);
+                }
+                OpCode::Equal => self.binary(BinOpType::EqualEqual, start)?,
+                OpCode::NotEqual => self.binary(BinOpType::NotEqual, start)?,
+                OpCode::Greater => self.binary(BinOpType::Greater, start)?,
+                OpCode::GreaterEqual => self.binary(BinOpType::GreaterEqual, start)?,
+                OpCode::Less => self.binary(BinOpType::Less, start)?,
+                OpCode::LessEqual => self.binary(BinOpType::LessEqual, start)?,
+                OpCode::Add => self.binary(BinOpType::Add, start)?,
+                OpCode::Subtract => self.binary(BinOpType::Sub, start)?,
+                OpCode::Multiply => self.binary(BinOpType::Mult, start)?,
+                OpCode::Divide => self.binary(BinOpType::Div, start)?,
+                OpCode::Not => self.unary(UniOpType::Bang, start)?,
+                OpCode::Negate => self.unary(UniOpType::Minus, start)?,
+                OpCode::CheckBool => {
+                    let check = Check::from_byte(self.read_byte());
+                    let val = self.peek(0);
+                    let span = PENDING_SPAN;
+                    let result = match check {
+                        Check::If => ops::condition("if", val, span),
+                        Check::While => ops::condition("while", val, span),
+                        Check::For => ops::condition("for", val, span),
+                        Check::Ternary => ops::condition("?", val, span),
+                        Check::And => ops::logical_operand(LogicOpType::And, val, span),
+                        Check::Or => ops::logical_operand(LogicOpType::Or, val, span),
+                    };
+                    self.locate(result, start)?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if let Value::Bool(false) = self.peek(0) {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::JumpIfTrue => {
+                    let offset = self.read_u16() as usize;
+                    if let Value::Bool(true) = self.peek(0) {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Print => {
+                    let val = self.pop();
+                    println!("{val}");
+                }
+                OpCode::Call => {
+                    let argc = self.read_byte() as usize;
+                    let result = self.call(argc, PENDING_SPAN);
+                    self.locate(result, start)?;
+                }
+                OpCode::Closure => {
+                    let Constant::Function(proto) = self.read_constant() else {
+                        unreachable!("Closure always refers to a function")
+                    };
+                    let base = self.frame().base;
+                    let mut upvalues = Vec::with_capacity(proto.upvalues.len());
+                    for upvalue in &proto.upvalues {
+                        if upvalue.is_local {
+                            upvalues.push(self.capture_upvalue(base + upvalue.index as usize));
+                        } else {
+                            let enclosing = &self.frame().closure.upvalues;
+                            upvalues.push(enclosing[upvalue.index as usize].clone());
+                        }
+                    }
+                    let closure = Closure { proto, upvalues };
+                    self.stack.push(Value::Closure(Rc::new(closure)));
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("a frame is running");
+                    self.close_upvalues(frame.base);
+                    //try statements in the returning function are left as well
+                    let depth = self.frames.len();
+                    while self.handlers.last().is_some_and(|h| h.frame == depth) {
+                        self.handlers.pop();
+                    }
+                    self.stack.truncate(frame.base);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_name();
+                    let count = self.read_byte() as usize;
+                    let methods = self
+                        .stack
+                        .split_off(self.stack.len() - count)
+                        .into_iter()
+                        .map(|method| match &method {
+                            Value::Closure(closure) => (closure.proto.name.clone(), method),
+                            _ => unreachable!("methods are always closures"),
+                        })
+                        .collect();
+                    let class = Class {
+                        name: name.to_string(),
+                        methods,
+                    };
+                    self.stack.push(Value::Class(Rc::new(class)));
+                }
+                OpCode::List => {
+                    let count = self.read_u16() as usize;
+                    let elements = self.stack.split_off(self.stack.len() - count);
+                    self.stack
+                        .push(Value::List(Rc::new(RefCell::new(elements))));
+                }
+                OpCode::Map => {
+                    let map = Map::default();
+                    self.stack.push(Value::Map(Rc::new(RefCell::new(map))));
+                }
+                OpCode::MapEntry => {
+                    let val = self.pop();
+                    let key = self.pop();
+                    let key = self.locate(ops::map_key(&key, PENDING_SPAN), start)?;
+                    if let Value::Map(map) = self.peek(0) {
+                        map.borrow_mut().insert(key, val);
+                    }
+                }
+                OpCode::Throw => {
+                    let val = self.pop();
+                    return Err(RuntimeError::thrown(val, self.span_at(start)));
+                }
+                OpCode::PushHandler => {
+                    let offset = self.read_u16() as usize;
+                    let handler = Handler {
+                        frame: self.frames.len() - 1,
+                        stack_len: self.stack.len(),
+                        ip: self.frame().ip + offset,
+                    };
+                    self.handlers.push(handler);
+                }
+                OpCode::PopHandler => {
+                    self.handlers.pop();
+                }
+            }
+        }
+    }
+
+    fn binary(&mut self, op: BinOpType, start: usize) -> Result<(), RuntimeError> {
+        let r = self.pop();
+        let l = self.pop();
+        let val = self.locate(ops::binary(op, &l, &r, PENDING_SPAN), start)?;
+        self.stack.push(val);
+        Ok(())
+    }
+
+    fn unary(&mut self, op: UniOpType, start: usize) -> Result<(), RuntimeError> {
+        let val = self.pop();
+        let val = self.locate(ops::unary(op, &val, PENDING_SPAN), start)?;
+        self.stack.push(val);
+        Ok(())
+    }
+
+    //the callee is below its arguments on the stack, span is the '(' of the call
+    fn call(&mut self, argc: usize, span: Span) -> Result<(), RuntimeError> {
+        let base = self.stack.len() - argc - 1;
+        match self.stack[base].clone() {
+            Value::Closure(closure) => self.call_closure(closure, argc, span),
+            Value::BoundMethod(bound) => {
+                self.stack[base] = Value::Instance(bound.receiver.clone());
+                self.call_closure(bound.method.clone(), argc, span)
+            }
+            //calling a class creates an instance and runs init on it, if there is one
+            Value::Class(class) => {
+                let instance = Rc::new(RefCell::new(Instance {
+                    class: class.clone(),
+                    fields: HashMap::new(),
+                }));
+                self.stack[base] = Value::Instance(instance);
+                match class.methods.get("init") {
+                    Some(Value::Closure(init)) => self.call_closure(init.clone(), argc, span),
+                    _ if argc != 0 => Err(ops::arity_mismatch(&class.name, 0, argc, span)),
+                    _ => Ok(()),
+                }
+            }
+            Value::Native(func) => {
+                let args = self.stack.split_off(base + 1);
+                let val = ops::call_native(func.as_ref(), args, span)?;
+                self.stack[base] = val;
+                Ok(())
+            }
+            callee => Err(ops::not_callable(&callee, span)),
+        }
+    }
+
+    fn call_closure(
+        &mut self,
+        closure: Rc<Closure>,
+        argc: usize,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        let proto = &closure.proto;
+        if argc != proto.arity {
+            return Err(ops::arity_mismatch(&proto.name, proto.arity, argc, span));
+        }
+        //the script's frame doesn't count as a call
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(ops::stack_overflow(&proto.name, span));
+        }
+        self.frames.push(Frame {
+            closure,
+            ip: 0,
+            base: self.stack.len() - argc - 1,
+        });
+        Ok(())
+    }
+
+    //reuses the open upvalue for the slot, so closures share the variable
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        for upvalue in &self.open_upvalues {
+            if let Upvalue::Open(open) = *upvalue.borrow() {
+                if open == slot {
+                    return upvalue.clone();
+                }
+            }
+        }
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
+
+    //moves the variables at or above the stack index into their upvalues
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) => slot,
+                Upvalue::Closed(_) => return false,
+            };
+            if slot < from {
+                return true;
+            }
+            *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+            false
+        });
+    }
+
+    // helper functions
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("a frame is running")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("a frame is running")
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.closure.proto.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let high = self.read_byte() as u16;
+        let low = self.read_byte() as u16;
+        (high << 8) | low
+    }
+
+    fn read_constant(&mut self) -> Constant {
+        let index = self.read_u16() as usize;
+        self.frame().closure.proto.chunk.constants[index].clone()
+    }
+
+    fn read_name(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Constant::String(name) => name,
+            _ => unreachable!("names are always string constants"),
+        }
+    }
+
+    //the span of the instruction starting at the offset in the running function
+    fn span_at(&self, offset: usize) -> Span {
+        self.frame().closure.proto.chunk.span_at(offset)
+    }
+
+    //gives an error made with PENDING_SPAN the span of the instruction at start
+    fn locate<T>(&self, result: Result<T, RuntimeError>, start: usize) -> Result<T, RuntimeError> {
+        result.map_err(|mut err| {
+            err.span = self.span_at(start);
+            err
+        })
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack
+            .pop()
+            .expect("the compiler keeps the stack balanced")
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+}
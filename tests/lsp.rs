@@ -0,0 +1,126 @@
+//a scripted editor session with the language server, every message is sent up front
+//and the responses and notifications it writes back are compared in order
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const URI: &str = "file:///session.aprn";
+
+//𝑥 is outside the basic multilingual plane, so it is two utf-16 code units for the editor
+const OPENED: &str =
+    "var 𝑥 = 1;\\nfunc twice(n) {\\n    return n * 2;\\n}\\nprint twice(𝑥) + 𝑥;\\n";
+const CHANGED: &str = "var 𝑥 = 1 +;\\n";
+
+fn frame(body: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+fn request(id: usize, method: &str, params: &str) -> String {
+    frame(&format!(
+        r#"{{"jsonrpc":"2.0","id":{id},"method":"{method}","params":{params}}}"#
+    ))
+}
+
+fn notification(method: &str, params: &str) -> String {
+    frame(&format!(
+        r#"{{"jsonrpc":"2.0","method":"{method}","params":{params}}}"#
+    ))
+}
+
+fn position(line: usize, character: usize) -> String {
+    format!(
+        r#"{{"textDocument":{{"uri":"{URI}"}},"position":{{"line":{line},"character":{character}}}}}"#
+    )
+}
+
+//the bodies of the framed messages in the output
+fn messages(mut output: &str) -> Vec<&str> {
+    let mut messages = Vec::new();
+    while let Some((headers, rest)) = output.split_once("\r\n\r\n") {
+        let length: usize = headers
+            .strip_prefix("Content-Length: ")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or_else(|| panic!("bad headers {headers:?}"));
+        messages.push(&rest[..length]);
+        output = &rest[length..];
+    }
+    assert_eq!(output, "", "unframed output");
+    messages
+}
+
+#[test]
+fn session() {
+    let input = [
+        request(1, "initialize", r#"{"capabilities":{}}"#),
+        notification("initialized", "{}"),
+        notification(
+            "textDocument/didOpen",
+            &format!(
+                r#"{{"textDocument":{{"uri":"{URI}","languageId":"apprentice","version":1,"text":"{OPENED}"}}}}"#
+            ),
+        ),
+        //the second 𝑥 on the last line starts at utf-16 character 18
+        request(2, "textDocument/hover", &position(4, 18)),
+        request(3, "textDocument/definition", &position(4, 18)),
+        request(4, "textDocument/definition", &position(2, 11)),
+        request(5, "textDocument/hover", &position(3, 0)),
+        request(
+            6,
+            "textDocument/documentSymbol",
+            &format!(r#"{{"textDocument":{{"uri":"{URI}"}}}}"#),
+        ),
+        notification(
+            "textDocument/didChange",
+            &format!(
+                r#"{{"textDocument":{{"uri":"{URI}","version":2}},"contentChanges":[{{"text":"{CHANGED}"}}]}}"#
+            ),
+        ),
+        request(7, "textDocument/rename", &position(0, 4)),
+        request(8, "shutdown", "null"),
+        request(9, "textDocument/hover", &position(0, 4)),
+        notification("exit", "null"),
+    ]
+    .concat();
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_apprentice"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    server
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = server.wait_with_output().unwrap();
+    //exit after shutdown is a clean exit
+    assert_eq!(output.status.code(), Some(0));
+    let output = String::from_utf8(output.stdout).unwrap();
+
+    let opened = r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///session.aprn","diagnostics":[]}}"#;
+    //the ; is character 12, not 11, because 𝑥 is two code units
+    let changed = r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///session.aprn","diagnostics":[{"range":{"start":{"line":0,"character":12},"end":{"line":0,"character":13}},"severity":1,"code":"E0105","source":"apprentice","message":"Expected expression","relatedInformation":[{"location":{"uri":"file:///session.aprn","range":{"start":{"line":0,"character":12},"end":{"line":0,"character":13}}},"message":"found ';'"}]}]}}"#;
+    let expected = [
+        concat!(
+            r#"{"jsonrpc":"2.0","id":1,"result":{"capabilities":{"textDocumentSync":1,"hoverProvider":true,"definitionProvider":true,"documentSymbolProvider":true},"serverInfo":{"name":"apprentice","version":""#,
+            env!("CARGO_PKG_VERSION"),
+            r#""}}}"#
+        ),
+        opened,
+        r#"{"jsonrpc":"2.0","id":2,"result":{"contents":{"kind":"markdown","value":"```apprentice\nvar 𝑥 = 1;\n```\n`𝑥` is declared at line 1, column 5"},"range":{"start":{"line":4,"character":18},"end":{"line":4,"character":20}}}}"#,
+        r#"{"jsonrpc":"2.0","id":3,"result":{"uri":"file:///session.aprn","range":{"start":{"line":0,"character":4},"end":{"line":0,"character":6}}}}"#,
+        r#"{"jsonrpc":"2.0","id":4,"result":{"uri":"file:///session.aprn","range":{"start":{"line":1,"character":11},"end":{"line":1,"character":12}}}}"#,
+        r#"{"jsonrpc":"2.0","id":5,"result":null}"#,
+        r#"{"jsonrpc":"2.0","id":6,"result":[{"name":"𝑥","kind":13,"range":{"start":{"line":0,"character":4},"end":{"line":0,"character":6}},"selectionRange":{"start":{"line":0,"character":4},"end":{"line":0,"character":6}},"children":[]},{"name":"twice","kind":12,"range":{"start":{"line":1,"character":5},"end":{"line":1,"character":10}},"selectionRange":{"start":{"line":1,"character":5},"end":{"line":1,"character":10}},"children":[]}]}"#,
+        changed,
+        r#"{"jsonrpc":"2.0","id":7,"error":{"code":-32601,"message":"unknown method 'textDocument/rename'"}}"#,
+        r#"{"jsonrpc":"2.0","id":8,"result":null}"#,
+        r#"{"jsonrpc":"2.0","id":9,"error":{"code":-32600,"message":"the server is shutting down"}}"#,
+    ];
+    let messages = messages(&output);
+    assert_eq!(messages.len(), expected.len(), "{messages:#?}");
+    for (message, expected) in messages.iter().zip(expected) {
+        assert_eq!(*message, expected);
+    }
+}
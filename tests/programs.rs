@@ -0,0 +1,61 @@
+//runs every program in tests/programs on both backends and compares what it printed
+//with the .stdout and .stderr files next to it, and its exit code with the .exit file,
+//a missing file means nothing was printed or the exit code was 0
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn expected(program: &Path, extension: &str) -> String {
+    fs::read_to_string(program.with_extension(extension)).unwrap_or_default()
+}
+
+#[test]
+fn programs() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut programs: Vec<_> = fs::read_dir(root.join("tests/programs"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "aprn"))
+        .collect();
+    programs.sort();
+    assert!(!programs.is_empty(), "no programs in tests/programs");
+
+    let mut failures = Vec::new();
+    for program in &programs {
+        //the path is relative so the one printed in errors doesn't depend on the checkout
+        let relative = program.strip_prefix(root).unwrap();
+        let stdout = expected(program, "stdout");
+        let stderr = expected(program, "stderr");
+        let code: i32 = match expected(program, "exit").trim() {
+            "" => 0,
+            code => code.parse().unwrap(),
+        };
+
+        for backend in ["tree", "vm"] {
+            let output = Command::new(env!("CARGO_BIN_EXE_apprentice"))
+                .current_dir(root)
+                .arg("run")
+                .arg(format!("--backend={backend}"))
+                .arg(relative)
+                .output()
+                .unwrap();
+            let name = format!("{} on --backend={backend}", relative.display());
+
+            let actual = String::from_utf8_lossy(&output.stdout);
+            if actual != stdout {
+                failures.push(format!("{name}: stdout\n{actual}\nexpected\n{stdout}"));
+            }
+            let actual = String::from_utf8_lossy(&output.stderr);
+            if actual != stderr {
+                failures.push(format!("{name}: stderr\n{actual}\nexpected\n{stderr}"));
+            }
+            if output.status.code() != Some(code) {
+                failures.push(format!(
+                    "{name}: exit code {:?}, expected {code}",
+                    output.status.code()
+                ));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
@@ -0,0 +1,78 @@
+//a scripted repl session, every line is sent up front and the prompts, values and
+//diagnostics it writes back are compared
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn session() {
+    let input = [
+        //the missing ';' is added
+        "var a = 1",
+        //bare expressions echo their value, assignments and declarations don't
+        "a + 1",
+        //unbalanced brackets and open strings wait for more lines
+        "func f(n) {",
+        "  return n * 2;",
+        "}",
+        "f(a)",
+        "print \"two",
+        "lines\";",
+        //errors are reported and the session goes on with what was defined before them
+        "a = a / \"x\";",
+        "print a +;",
+        "a",
+        "[1,",
+        "2]",
+    ]
+    .map(|line| format!("{line}\n"))
+    .concat();
+
+    let mut repl = Command::new(env!("CARGO_BIN_EXE_apprentice"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    repl.stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = repl.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+
+    //a prompt for every line, "... " while the input is unfinished
+    let expected = [
+        "> > 2",
+        "> ... ... > 2",
+        "> ... two",
+        "lines",
+        "> > > 1",
+        "> ... [1, 2]",
+        "> ",
+        "",
+    ];
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        expected.join("\n")
+    );
+
+    let expected = [
+        "error[E0201]: Can't apply '/' to a number and a string",
+        " --> <repl>:1:7",
+        "  |",
+        "1 | a = a / \"x\";",
+        "  |       ^",
+        "error[E0105]: Expected expression",
+        " --> <repl>:1:10",
+        "  |",
+        "1 | print a +;",
+        "  |          ^ found ';'",
+        "",
+    ];
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        expected.join("\n")
+    );
+}